@@ -0,0 +1,214 @@
+use embedded_can::Frame;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::frame::{CountedFrame, FrameSetKey, TimestampedFrame};
+
+/// Values seen for each bit of the payloads of an ID, as masks of the bits
+/// seen set and of the bits seen cleared in each byte
+#[derive(Clone, Default)]
+pub struct ObservedBits {
+    ones: Vec<u8>,
+    zeros: Vec<u8>,
+}
+
+impl ObservedBits {
+    pub fn observe(&mut self, data: &[u8]) {
+        if self.ones.len() < data.len() {
+            self.ones.resize(data.len(), 0);
+            self.zeros.resize(data.len(), 0);
+        }
+
+        for (i, byte) in data.iter().enumerate() {
+            self.ones[i] |= byte;
+            self.zeros[i] |= !byte;
+        }
+    }
+
+    /// Bits of the given byte which were seen set or cleared, but never
+    /// with that value here
+    fn get_unseen_bits(&self, i: usize, ones: u8, zeros: u8) -> u8 {
+        let seen_ones = self.ones.get(i).copied().unwrap_or(0);
+        let seen_zeros = self.zeros.get(i).copied().unwrap_or(0);
+        (ones & !seen_ones) | (zeros & !seen_zeros)
+    }
+
+    /// Bits of each byte which took a value never seen in the baseline
+    fn get_deviating_bits(&self, baseline: &ObservedBits) -> Vec<u8> {
+        (0..self.ones.len())
+            .map(|i| baseline.get_unseen_bits(i, self.ones[i], self.zeros[i]))
+            .collect()
+    }
+}
+
+/// Snapshot of the IDs and the bit values seen for them while the bus was
+/// idle, which the frames captured afterwards are compared against to find
+/// out what an action changed
+pub struct Baseline {
+    observed_bits: HashMap<FrameSetKey, ObservedBits>,
+}
+
+impl Baseline {
+    pub fn record(frame_set: &HashMap<FrameSetKey, CountedFrame>) -> Self {
+        Self {
+            observed_bits: frame_set
+                .iter()
+                .map(|(key, counted_frame)| (key.clone(), counted_frame.observed_bits.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn get_id_count(&self) -> usize {
+        self.observed_bits.len()
+    }
+
+    /// Bits of each payload byte of the ID which took a value never seen in
+    /// the baseline, or `None` if the ID is not in the baseline at all
+    pub fn get_deviating_bits(&self, key: &FrameSetKey, frame: &CountedFrame) -> Option<Vec<u8>> {
+        self.observed_bits
+            .get(key)
+            .map(|baseline| frame.observed_bits.get_deviating_bits(baseline))
+    }
+
+    /// Whether the ID is new, or any of its bits took a value never seen in
+    /// the baseline
+    pub fn is_deviating(&self, key: &FrameSetKey, frame: &CountedFrame) -> bool {
+        self.get_deviating_bits(key, frame)
+            .is_none_or(|deviating_bits| deviating_bits.iter().any(|bits| *bits != 0))
+    }
+
+    /// Whether the frame has an ID which is not in the baseline, or a payload
+    /// bit with a value never seen in the baseline
+    pub fn is_frame_deviating(&self, frame: &TimestampedFrame) -> bool {
+        let key = (Arc::clone(&frame.interface), frame.frame.id());
+        let Some(baseline) = self.observed_bits.get(&key) else {
+            return true;
+        };
+
+        frame
+            .frame
+            .data()
+            .iter()
+            .enumerate()
+            .any(|(i, byte)| baseline.get_unseen_bits(i, *byte, !byte) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_can::{ExtendedId, StandardId};
+    use socketcan::{CanAnyFrame, CanDataFrame, CanTimestamps};
+
+    fn data_frame(id: embedded_can::Id, data: &[u8]) -> CanAnyFrame {
+        CanAnyFrame::Normal(CanDataFrame::new(id, data).unwrap())
+    }
+
+    fn standard_id(id: u16) -> embedded_can::Id {
+        embedded_can::Id::Standard(StandardId::new(id).unwrap())
+    }
+
+    /// ID whose payloads were seen with the given values
+    fn counted_frame(id: embedded_can::Id, payloads: &[&[u8]]) -> CountedFrame {
+        let mut counted_frame = CountedFrame::new(data_frame(id, payloads[0]), "mem0".into());
+        for data in payloads {
+            counted_frame.observed_bits.observe(data);
+        }
+        counted_frame
+    }
+
+    fn timestamped_frame(id: embedded_can::Id, data: &[u8]) -> TimestampedFrame {
+        TimestampedFrame::new(
+            data_frame(id, data),
+            "mem0".into(),
+            0,
+            CanTimestamps::default(),
+        )
+    }
+
+    /// Baseline in which bit 0 of the only byte of ID 0x100 toggled, and the
+    /// other bits stayed cleared
+    fn record_baseline() -> Baseline {
+        let key: FrameSetKey = ("mem0".into(), standard_id(0x100));
+        let frame_set =
+            HashMap::from([(key, counted_frame(standard_id(0x100), &[&[0x00], &[0x01]]))]);
+        Baseline::record(&frame_set)
+    }
+
+    #[test]
+    fn observed_bits_track_both_values() {
+        let mut baseline = ObservedBits::default();
+        baseline.observe(&[0x0F]);
+        baseline.observe(&[0x03]);
+
+        // Bits 0 and 1 were always set, bits 2 and 3 toggled, and the other
+        // bits were always cleared
+        let mut observed_bits = ObservedBits::default();
+        observed_bits.observe(&[0x1F]);
+        assert_eq!(observed_bits.get_deviating_bits(&baseline), [0x10]);
+
+        let mut observed_bits = ObservedBits::default();
+        observed_bits.observe(&[0x01]);
+        observed_bits.observe(&[0x0D]);
+        assert_eq!(observed_bits.get_deviating_bits(&baseline), [0x02]);
+    }
+
+    #[test]
+    fn new_ids_deviate() {
+        let baseline = record_baseline();
+        assert_eq!(baseline.get_id_count(), 1);
+
+        for id in [
+            standard_id(0x200),
+            embedded_can::Id::Extended(ExtendedId::new(0x100).unwrap()),
+        ] {
+            let key: FrameSetKey = ("mem0".into(), id);
+            let frame = counted_frame(id, &[&[0x00]]);
+            assert_eq!(baseline.get_deviating_bits(&key, &frame), None);
+            assert!(baseline.is_deviating(&key, &frame));
+            assert!(baseline.is_frame_deviating(&timestamped_frame(id, &[0x00])));
+        }
+
+        // The same ID on another interface is a different ID
+        let key: FrameSetKey = ("mem1".into(), standard_id(0x100));
+        assert!(baseline.is_deviating(&key, &counted_frame(standard_id(0x100), &[&[0x00]])));
+    }
+
+    #[test]
+    fn bits_first_seen_after_the_baseline_deviate() {
+        let baseline = record_baseline();
+        let key: FrameSetKey = ("mem0".into(), standard_id(0x100));
+
+        // Toggling bit 0 was already seen in the baseline
+        let frame = counted_frame(standard_id(0x100), &[&[0x01], &[0x00]]);
+        assert_eq!(baseline.get_deviating_bits(&key, &frame), Some(vec![0x00]));
+        assert!(!baseline.is_deviating(&key, &frame));
+        assert!(!baseline.is_frame_deviating(&timestamped_frame(standard_id(0x100), &[0x01])));
+
+        let frame = counted_frame(standard_id(0x100), &[&[0x01], &[0x03]]);
+        assert_eq!(baseline.get_deviating_bits(&key, &frame), Some(vec![0x02]));
+        assert!(baseline.is_deviating(&key, &frame));
+        assert!(baseline.is_frame_deviating(&timestamped_frame(standard_id(0x100), &[0x03])));
+    }
+
+    #[test]
+    fn payloads_longer_than_the_baseline_deviate() {
+        let baseline = record_baseline();
+        let key: FrameSetKey = ("mem0".into(), standard_id(0x100));
+
+        // Every bit of a byte the baseline never saw is new, whatever its value
+        let frame = counted_frame(standard_id(0x100), &[&[0x01, 0x00]]);
+        assert_eq!(
+            baseline.get_deviating_bits(&key, &frame),
+            Some(vec![0x00, 0xFF])
+        );
+        assert!(baseline.is_deviating(&key, &frame));
+        assert!(baseline.is_frame_deviating(&timestamped_frame(standard_id(0x100), &[0x01, 0x00])));
+
+        // A shorter payload only has the bits the baseline already saw
+        let frame = counted_frame(standard_id(0x100), &[&[]]);
+        assert!(!baseline.is_deviating(&key, &frame));
+        assert!(!baseline.is_frame_deviating(&timestamped_frame(standard_id(0x100), &[])));
+    }
+}
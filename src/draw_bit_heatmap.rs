@@ -0,0 +1,201 @@
+use embedded_can::Frame;
+use ratatui::{
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    symbols::border,
+    text::{Line, Text},
+    widgets::{Block, BorderType, Borders, Cell, Paragraph, Row, Table},
+};
+use socketcan::CanAnyFrame;
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    frame::{BitChanges, CountedFrame},
+    App,
+};
+
+fn get_header_for_bit_grid(header_style: Style) -> Row<'static> {
+    std::iter::once(String::from("Byte"))
+        .chain((0..8).rev().map(|bit| format!("Bit {}", bit)))
+        .map(Cell::from)
+        .collect::<Row>()
+        .style(header_style)
+}
+
+/// Heat of a bit which toggled the given share of the times the most toggled
+/// bit of the payload did, from blue for rarely toggled bits to red for the
+/// most toggled ones
+fn get_toggle_heat_style(toggle_share: f64) -> Style {
+    match toggle_share {
+        share if share <= 0.25 => Style::default().fg(Color::White).bg(Color::Blue),
+        share if share <= 0.5 => Style::default().fg(Color::Black).bg(Color::Cyan),
+        share if share <= 0.75 => Style::default().fg(Color::Black).bg(Color::Yellow),
+        _ => Style::default().fg(Color::White).bg(Color::Red),
+    }
+}
+
+/// Cell of a bit, showing its value and how often it toggled, along with how
+/// long ago it last toggled. Bits which match the baseline, or which never
+/// changed when there is no baseline to compare against, are dimmed out, and
+/// bits which toggled within the change highlight time stand out.
+fn get_bit_cell<'a>(
+    value: bool,
+    bit_changes: BitChanges,
+    is_deviating: Option<bool>,
+    max_toggle_count: u32,
+    now: Instant,
+    change_highlight_duration: Duration,
+) -> Cell<'a> {
+    let value = u8::from(value);
+    let age = bit_changes
+        .changed_at
+        .map(|changed_at| now.saturating_duration_since(changed_at));
+    let age_line = age.map_or_else(
+        || Line::from("-"),
+        |age| Line::from(format!("{:.1} s", age.as_secs_f64())),
+    );
+    let text = Text::from(vec![
+        Line::from(format!("{} x{}", value, bit_changes.toggle_count)),
+        age_line,
+    ]);
+
+    if !is_deviating.unwrap_or(age.is_some()) {
+        return Cell::from(text).style(Style::default().fg(Color::DarkGray));
+    }
+
+    let style = get_toggle_heat_style(
+        f64::from(bit_changes.toggle_count) / f64::from(max_toggle_count.max(1)),
+    );
+    let style = match age.is_some_and(|age| age < change_highlight_duration) {
+        true => style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+        false => style,
+    };
+
+    Cell::from(text).style(style)
+}
+
+/// Rows of the bit grid, one for each payload byte with its most significant
+/// bit first, along with the bits of each byte which deviate from the
+/// baseline if the payload is compared against one
+fn get_bit_grid_rows<'a>(
+    frame: &CountedFrame,
+    deviating_bits: Option<&[u8]>,
+    now: Instant,
+    change_highlight_duration: Duration,
+) -> Vec<Row<'a>> {
+    let max_toggle_count = frame
+        .bit_changes
+        .iter()
+        .map(|bit_changes| bit_changes.toggle_count)
+        .max()
+        .unwrap_or(0);
+
+    frame
+        .frame
+        .data()
+        .iter()
+        .enumerate()
+        .map(|(byte_index, byte)| {
+            let cells = (0..8).rev().map(|bit| {
+                let bit_changes = frame
+                    .bit_changes
+                    .get(byte_index * 8 + bit)
+                    .copied()
+                    .unwrap_or_default();
+
+                let is_deviating = deviating_bits.map(|deviating_bits| {
+                    deviating_bits.get(byte_index).copied().unwrap_or(0) & (1 << bit) != 0
+                });
+
+                get_bit_cell(
+                    byte & (1 << bit) != 0,
+                    bit_changes,
+                    is_deviating,
+                    max_toggle_count,
+                    now,
+                    change_highlight_duration,
+                )
+            });
+
+            std::iter::once(Cell::from(format!("{} (0x{:02x})", byte_index, byte)))
+                .chain(cells)
+                .collect::<Row>()
+                .height(2)
+        })
+        .collect()
+}
+
+/// Payload of the ID selected in the frame set as a grid of bits, each shown
+/// with its toggle count and the time since it last toggled, coloured by how
+/// often it toggles compared to the other bits of the payload
+pub fn draw_bit_heatmap(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Double)
+        .border_set(border::THICK);
+
+    let (Some(key), Some(frame)) = (
+        app.selected_frame_set_key.as_ref(),
+        app.get_selected_counted_frame(),
+    ) else {
+        let hint = Paragraph::new("Select an ID in the frame set to see its bits")
+            .block(block.title(" Bit Heatmap "));
+        f.render_widget(hint, area);
+        return;
+    };
+
+    let title = format!(
+        " Bit Heatmap: {} 0x{:x} ",
+        frame.interface,
+        socketcan::Frame::raw_id(&frame.frame)
+    );
+
+    if let CanAnyFrame::Remote(_) = frame.frame {
+        let block = block.title(title);
+        let hint =
+            Paragraph::new("Only remote requests have been captured for this ID").block(block);
+        f.render_widget(hint, area);
+        return;
+    }
+
+    let header_style = Style::default().fg(Color::White).bg(Color::Black);
+    // IDs which are not in the baseline deviate from it as a whole, so their
+    // bits are shown as if there were no baseline
+    let deviating_bits = app
+        .get_changes_only_baseline()
+        .and_then(|baseline| baseline.get_deviating_bits(key, frame));
+    let rows = get_bit_grid_rows(
+        frame,
+        deviating_bits.as_deref(),
+        app.get_display_time(),
+        app.change_highlight_duration,
+    );
+
+    // The borders and the header take up three lines, and each byte two, so
+    // FD payloads rarely fit and are scrolled through a few bytes at a time
+    let byte_count = rows.len();
+    let first_byte = app.bit_heatmap_scroll.min(byte_count.saturating_sub(1));
+    let visible_bytes = usize::from(area.height.saturating_sub(3) / 2);
+    let title = match first_byte > 0 || first_byte + visible_bytes < byte_count {
+        true => format!(
+            "{}(bytes {}-{} of {}, <[/]> to scroll) ",
+            title,
+            first_byte,
+            (first_byte + visible_bytes)
+                .min(byte_count)
+                .saturating_sub(1),
+            byte_count
+        ),
+        false => title,
+    };
+    let block = block.title(title);
+
+    let table = Table::new(
+        rows.into_iter().skip(first_byte),
+        std::iter::once(Constraint::Length(10)).chain([Constraint::Fill(1); 8]),
+    )
+    .header(get_header_for_bit_grid(header_style))
+    .block(block);
+    f.render_widget(table, area);
+}
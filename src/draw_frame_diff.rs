@@ -0,0 +1,208 @@
+use embedded_can::Frame;
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    symbols::border,
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, Cell, Paragraph, Row, Table},
+};
+
+use crate::{
+    frame::{format_timestamp, TimestampedFrame},
+    App,
+};
+
+fn get_header_for_byte_diff(header_style: Style) -> Row<'static> {
+    ["Byte", "Marked", "Marked Bits", "Selected", "Selected Bits"]
+        .into_iter()
+        .map(Cell::from)
+        .collect::<Row>()
+        .style(header_style)
+}
+
+fn get_changed_style() -> Style {
+    Style::default().fg(Color::White).bg(Color::Red)
+}
+
+/// Byte of one of the compared payloads as hex, or "-" past its end
+fn get_byte_cell<'a>(byte: Option<u8>, is_changed: bool) -> Cell<'a> {
+    let text = byte.map_or_else(|| String::from("-"), |byte| format!("{:02x}", byte));
+    match is_changed {
+        true => Cell::from(Span::styled(text, get_changed_style())),
+        false => Cell::from(text),
+    }
+}
+
+/// Bits of a byte of one of the compared payloads, most significant bit first,
+/// with the bits which differ from the other payload standing out
+fn get_bits_cell<'a>(byte: Option<u8>, changed_bits: u8) -> Cell<'a> {
+    let Some(byte) = byte else {
+        return Cell::from("-");
+    };
+
+    let spans = (0..8)
+        .rev()
+        .map(|bit| {
+            let text = format!("{}", (byte >> bit) & 1);
+            match changed_bits & (1 << bit) != 0 {
+                true => Span::styled(text, get_changed_style()),
+                false => Span::from(text),
+            }
+        })
+        .collect::<Vec<Span>>();
+
+    Cell::from(Line::from(spans))
+}
+
+/// Rows comparing the payloads byte by byte, up to the end of the longer one.
+/// Bytes past the end of the shorter payload count as changed, all of their
+/// bits being shown as changed in the longer payload.
+fn get_byte_diff_rows<'a>(marked_data: &[u8], selected_data: &[u8]) -> Vec<Row<'a>> {
+    (0..marked_data.len().max(selected_data.len()))
+        .map(|i| {
+            let marked_byte = marked_data.get(i).copied();
+            let selected_byte = selected_data.get(i).copied();
+            let changed_bits = match (marked_byte, selected_byte) {
+                (Some(marked_byte), Some(selected_byte)) => marked_byte ^ selected_byte,
+                _ => 0xff,
+            };
+            let is_changed = marked_byte != selected_byte;
+
+            Row::new([
+                Cell::from(format!("{}", i)),
+                get_byte_cell(marked_byte, is_changed),
+                get_bits_cell(marked_byte, changed_bits),
+                get_byte_cell(selected_byte, is_changed),
+                get_bits_cell(selected_byte, changed_bits),
+            ])
+        })
+        .collect()
+}
+
+fn get_frame_summary(label: &str, frame: &TimestampedFrame) -> String {
+    format!(
+        "{}: #{} {} 0x{:x} {} at {} s",
+        label,
+        frame.frame_number,
+        frame.interface,
+        frame.get_numeric_id(),
+        frame.frame_type,
+        format_timestamp(frame.get_timestamp())
+    )
+}
+
+/// Time from the marked frame to the selected frame, which is negative when
+/// the selected frame came first
+fn format_time_delta(marked_frame: &TimestampedFrame, selected_frame: &TimestampedFrame) -> String {
+    let marked_timestamp = marked_frame.get_timestamp();
+    let selected_timestamp = selected_frame.get_timestamp();
+
+    match selected_timestamp.checked_sub(marked_timestamp) {
+        Some(delta) => format!("+{}", format_timestamp(delta)),
+        None => format!(
+            "-{}",
+            format_timestamp(marked_timestamp - selected_timestamp)
+        ),
+    }
+}
+
+/// Lines describing the compared frames, the time between them and how much
+/// of their payloads changed
+fn get_diff_summary_lines<'a>(
+    marked_frame: &TimestampedFrame,
+    selected_frame: &TimestampedFrame,
+) -> Vec<Line<'a>> {
+    let marked_data = marked_frame.frame.data();
+    let selected_data = selected_frame.frame.data();
+
+    let changed_bytes = (0..marked_data.len().max(selected_data.len()))
+        .filter(|i| marked_data.get(*i) != selected_data.get(*i))
+        .count();
+    let changed_bits = marked_data
+        .iter()
+        .zip(selected_data)
+        .map(|(marked_byte, selected_byte)| (marked_byte ^ selected_byte).count_ones())
+        .sum::<u32>();
+
+    let mut lines = vec![
+        Line::from(get_frame_summary("Marked", marked_frame)),
+        Line::from(get_frame_summary("Selected", selected_frame)),
+        Line::from(format!(
+            "Time delta: {} s, {} frames | Changed: {} bytes, {} bits",
+            format_time_delta(marked_frame, selected_frame),
+            selected_frame.frame_number as i128 - marked_frame.frame_number as i128,
+            changed_bytes,
+            changed_bits
+        )),
+    ];
+
+    if marked_frame.frame.id() != selected_frame.frame.id()
+        || marked_frame.interface != selected_frame.interface
+    {
+        lines.push(Line::styled(
+            "The frames are of different IDs",
+            Style::default().fg(Color::Yellow).bold(),
+        ));
+    }
+    if marked_data.len() != selected_data.len() {
+        lines.push(Line::styled(
+            format!(
+                "Payload length changed from {} to {} bytes",
+                marked_data.len(),
+                selected_data.len()
+            ),
+            Style::default().fg(Color::Yellow).bold(),
+        ));
+    }
+
+    lines
+}
+
+/// Payload of the selected frame of the frame list compared against the
+/// marked frame, byte by byte and bit by bit
+pub fn draw_frame_diff(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Double)
+        .border_set(border::THICK)
+        .title(" Diff Against Marked Frame ");
+
+    let Some(marked_frame) = &app.marked_frame else {
+        return;
+    };
+    let Some(selected_frame) = app.get_selected_frame() else {
+        let hint = Paragraph::new(vec![
+            Line::from(get_frame_summary("Marked", marked_frame)),
+            Line::from("Select a frame to compare it against the marked frame"),
+        ])
+        .block(block);
+        f.render_widget(hint, area);
+        return;
+    };
+
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    let summary_lines = get_diff_summary_lines(marked_frame, selected_frame);
+    let [summary_area, byte_diff_area] = Layout::vertical([
+        Constraint::Length(summary_lines.len() as u16),
+        Constraint::Fill(1),
+    ])
+    .areas(inner_area);
+
+    f.render_widget(Paragraph::new(summary_lines), summary_area);
+
+    let header_style = Style::default().fg(Color::White).bg(Color::Black);
+    let table = Table::new(
+        get_byte_diff_rows(marked_frame.frame.data(), selected_frame.frame.data()),
+        [
+            Constraint::Length(5),
+            Constraint::Length(8),
+            Constraint::Length(14),
+            Constraint::Length(9),
+            Constraint::Length(14),
+        ],
+    )
+    .header(get_header_for_byte_diff(header_style));
+    f.render_widget(table, byte_diff_area);
+}
@@ -0,0 +1,326 @@
+use embedded_can::Frame;
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style},
+    symbols::border,
+    text::Line,
+    widgets::{Block, BorderType, Borders, Cell, Paragraph, Row, Table},
+};
+use socketcan::CanAnyFrame;
+
+use crate::{
+    error_frame::decode_error_frame,
+    frame::{format_timestamp, get_data_len, get_fd_flags, FrameType},
+    App, FrameView,
+};
+
+/// Widths in bytes of the integers the payload is interpreted as
+const INTEGER_WIDTHS: [usize; 4] = [1, 2, 4, 8];
+
+/// Order of the bytes of an integer in the payload
+#[derive(Clone, Copy)]
+enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+impl std::fmt::Display for ByteOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ByteOrder::LittleEndian => write!(f, "LE"),
+            ByteOrder::BigEndian => write!(f, "BE"),
+        }
+    }
+}
+
+/// Unsigned integer made up of the given bytes, of at most 8 bytes
+fn get_unsigned(bytes: &[u8], byte_order: ByteOrder) -> u64 {
+    let accumulate = |value: u64, byte: &u8| (value << 8) | u64::from(*byte);
+    match byte_order {
+        ByteOrder::LittleEndian => bytes.iter().rev().fold(0, accumulate),
+        ByteOrder::BigEndian => bytes.iter().fold(0, accumulate),
+    }
+}
+
+/// The unsigned integer of the given width in bytes, as a two's complement
+/// signed integer
+fn get_signed(value: u64, width: usize) -> i64 {
+    let unused_bits = 64 - width as u32 * 8;
+    ((value << unused_bits) as i64) >> unused_bits
+}
+
+/// Formats a float in plain notation unless it is very large or very small
+fn format_float<T>(value: T) -> String
+where
+    T: Into<f64> + Copy + std::fmt::Display + std::fmt::LowerExp,
+{
+    match value.into().abs() {
+        magnitude if magnitude == 0.0 || (1e-4..1e9).contains(&magnitude) => {
+            format!("{}", value)
+        }
+        magnitude if magnitude.is_finite() => format!("{:e}", value),
+        _ => format!("{}", value),
+    }
+}
+
+/// IEEE 754 float of the given width in bytes, for the widths floats have
+fn get_float(value: u64, width: usize) -> Option<String> {
+    match width {
+        4 => Some(format_float(f32::from_bits(value as u32))),
+        8 => Some(format_float(f64::from_bits(value))),
+        _ => None,
+    }
+}
+
+fn get_header_for_interpretations(header_style: Style) -> Row<'static> {
+    ["Offset", "Type", "Unsigned", "Signed", "Float"]
+        .into_iter()
+        .map(Cell::from)
+        .collect::<Row>()
+        .style(header_style)
+}
+
+/// Byte orders an integer of the given width in bytes is shown in. Single
+/// bytes have no byte order, so they are only shown once.
+fn get_byte_orders(width: usize) -> &'static [ByteOrder] {
+    match width {
+        1 => &[ByteOrder::LittleEndian],
+        _ => &[ByteOrder::LittleEndian, ByteOrder::BigEndian],
+    }
+}
+
+/// Number of rows interpreting a payload of the given length
+pub fn get_interpretation_row_count(data_len: usize) -> usize {
+    INTEGER_WIDTHS
+        .into_iter()
+        .filter(|width| *width <= data_len)
+        .map(|width| (data_len - width + 1) * get_byte_orders(width).len())
+        .sum()
+}
+
+/// Rows interpreting the payload as every integer and float which fits in
+/// it, at every byte offset and in both byte orders
+fn get_interpretation_rows<'a>(data: &[u8]) -> Vec<Row<'a>> {
+    let mut rows = vec![];
+
+    for width in INTEGER_WIDTHS
+        .into_iter()
+        .filter(|width| *width <= data.len())
+    {
+        let byte_orders = get_byte_orders(width);
+
+        for (offset, bytes) in data.windows(width).enumerate() {
+            for byte_order in byte_orders {
+                let value = get_unsigned(bytes, *byte_order);
+                let bits = width * 8;
+                let data_type = match width {
+                    1 => format!("{}-bit", bits),
+                    _ => format!("{}-bit {}", bits, byte_order),
+                };
+
+                rows.push(Row::new([
+                    Cell::from(format!("{}", offset)),
+                    Cell::from(data_type),
+                    Cell::from(format!("{}", value)),
+                    Cell::from(format!("{}", get_signed(value, width))),
+                    Cell::from(get_float(value, width).unwrap_or_else(|| String::from("-"))),
+                ]));
+            }
+        }
+    }
+
+    rows
+}
+
+/// Lines of the payload formatted byte by byte, with the given number of
+/// bytes on each line, the first line being labelled and the others indented
+/// to line up with it
+fn get_payload_lines<'a>(
+    label: &str,
+    data: &[u8],
+    bytes_per_line: usize,
+    format_bytes: impl Fn(&[u8]) -> String,
+) -> Vec<Line<'a>> {
+    data.chunks(bytes_per_line)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let prefix = match i {
+                0 => format!("{}: ", label),
+                _ => " ".repeat(label.len() + 2),
+            };
+            Line::from(format!("{}{}", prefix, format_bytes(chunk)))
+        })
+        .collect()
+}
+
+/// Printable ASCII characters of the payload, with a dot for any other byte
+fn get_ascii(data: &[u8]) -> String {
+    data.iter()
+        .map(|byte| match byte.is_ascii_graphic() || *byte == b' ' {
+            true => char::from(*byte),
+            false => '.',
+        })
+        .collect()
+}
+
+/// Description of the ID, format and payload of the frame
+fn get_frame_lines<'a>(frame: &CanAnyFrame) -> Vec<Line<'a>> {
+    let id = socketcan::Frame::raw_id(frame);
+    let (format, id_bits) = match frame.is_extended() {
+        true => ("Extended (29-bit)", 29),
+        false => ("Standard (11-bit)", 11),
+    };
+
+    let mut lines = vec![
+        Line::from(format!(
+            "ID: 0x{:x} | {} | 0b{:0width$b}",
+            id,
+            id,
+            id,
+            width = id_bits
+        )),
+        Line::from(format!(
+            "Format: {} | Type: {} | DLC: {} | Len: {} | Flags: {}",
+            format,
+            FrameType::of(frame),
+            frame.dlc(),
+            get_data_len(frame),
+            get_fd_flags(frame)
+        )),
+    ];
+
+    match frame {
+        CanAnyFrame::Remote(_) => lines.push(Line::from("Remote request, without payload")),
+        CanAnyFrame::Error(error_frame) => lines.push(Line::from(format!(
+            "Errors: {}",
+            decode_error_frame(error_frame).join(", ")
+        ))),
+        _ => {}
+    }
+
+    // FD payloads are spread over several lines, with fewer bytes on each
+    // line of binary as they take up more room
+    let data = frame.data();
+    lines.extend(get_payload_lines("Hex", data, 16, |bytes| {
+        bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }));
+    lines.extend(get_payload_lines("Binary", data, 8, |bytes| {
+        bytes
+            .iter()
+            .map(|byte| format!("{:08b}", byte))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }));
+    lines.extend(get_payload_lines("ASCII", data, 16, get_ascii));
+
+    lines
+}
+
+/// Details of the frame selected in the frame list, or of the latest frame
+/// of the ID selected in the frame set, with its payload decoded as every
+/// integer and float it could hold
+pub fn draw_frame_inspector(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Double)
+        .border_set(border::THICK);
+
+    let selected_frame = match app.frame_view {
+        FrameView::FrameList => app.get_selected_frame().map(|frame| {
+            (
+                format!(
+                    " Frame #{} on {} at {} s ",
+                    frame.frame_number,
+                    frame.interface,
+                    format_timestamp(frame.get_timestamp())
+                ),
+                &frame.frame,
+            )
+        }),
+        FrameView::FrameSet => app.get_selected_counted_frame().map(|frame| {
+            (
+                format!(" Latest Frame of {} ", frame.interface),
+                &frame.frame,
+            )
+        }),
+    };
+
+    let Some((title, frame)) = selected_frame else {
+        let hint = Paragraph::new("Select a frame to inspect it").block(block.title(" Inspector "));
+        f.render_widget(hint, area);
+        return;
+    };
+
+    let inner_area = block.inner(area);
+    let frame_lines = get_frame_lines(frame);
+    let [frame_area, interpretations_area] = Layout::vertical([
+        Constraint::Length(frame_lines.len() as u16),
+        Constraint::Fill(1),
+    ])
+    .areas(inner_area);
+
+    // There are dozens of interpretations of a classic payload, and hundreds
+    // of an FD one, so they are scrolled through below the header
+    let rows = get_interpretation_rows(frame.data());
+    let row_count = rows.len();
+    let first_row = app.frame_inspector_scroll.min(row_count.saturating_sub(1));
+    let visible_rows = usize::from(interpretations_area.height.saturating_sub(1));
+    let block = match first_row > 0 || first_row + visible_rows < row_count {
+        true => block.title_bottom(format!(
+            " Rows {}-{} of {}, <</>> to scroll ",
+            first_row + 1,
+            (first_row + visible_rows).min(row_count),
+            row_count
+        )),
+        false => block,
+    };
+
+    f.render_widget(block.title(title), area);
+    f.render_widget(Paragraph::new(frame_lines), frame_area);
+
+    let header_style = Style::default().fg(Color::White).bg(Color::Black);
+    let table = Table::new(
+        rows.into_iter().skip(first_row),
+        [
+            Constraint::Length(6),
+            Constraint::Length(10),
+            Constraint::Fill(2),
+            Constraint::Fill(2),
+            Constraint::Fill(2),
+        ],
+    )
+    .header(get_header_for_interpretations(header_style));
+    f.render_widget(table, interpretations_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_interpretation_rows() {
+        assert_eq!(get_interpretation_row_count(0), 0);
+        assert_eq!(get_interpretation_row_count(8), 34);
+
+        for data_len in 0..=64 {
+            assert_eq!(
+                get_interpretation_row_count(data_len),
+                get_interpretation_rows(&vec![0; data_len]).len()
+            );
+        }
+    }
+
+    #[test]
+    fn interprets_integers_in_both_byte_orders() {
+        let bytes = [0xfe, 0xff];
+        assert_eq!(get_unsigned(&bytes, ByteOrder::LittleEndian), 0xfffe);
+        assert_eq!(get_unsigned(&bytes, ByteOrder::BigEndian), 0xfeff);
+        assert_eq!(get_signed(0xfffe, 2), -2);
+        assert_eq!(get_signed(0x7fff, 2), 0x7fff);
+        assert_eq!(get_signed(u64::MAX, 8), -1);
+    }
+}
@@ -2,22 +2,33 @@ use embedded_can::Frame;
 use ratatui::{
     layout::{Constraint, Rect},
     style::{Color, Style},
-    text::Text,
-    widgets::{Cell, Row, Table},
+    text::{Line, Span, Text},
+    widgets::{Cell, Row, Table, TableState},
 };
-use socketcan::CanFrame;
+use socketcan::CanAnyFrame;
+
+use std::time::{Duration, Instant};
 
 use crate::{
-    frame::{CountedFrame, TimestampedFrame},
+    error_frame::decode_error_frame,
+    frame::{format_timestamp, get_data_len, get_fd_flags, CountedFrame, TimestampedFrame},
     App,
 };
 
+/// Number of payload bytes shown per line before the data column wraps
+const PAYLOAD_BYTES_PER_LINE: usize = 16;
+
 fn get_header_for_timestamped_frames(header_style: Style) -> Row<'static> {
     [
         "Frame #",
-        "Timestamp",
+        "Timestamp (s)",
+        "HW Timestamp (s)",
+        "Interface",
         "ID",
+        "Type",
         "DLC",
+        "Len",
+        "Flags",
         "Extended",
         "Data (hex)",
     ]
@@ -28,50 +39,273 @@ fn get_header_for_timestamped_frames(header_style: Style) -> Row<'static> {
 }
 
 fn get_header_for_counted_frame_set(header_style: Style) -> Row<'static> {
-    ["ID", "DLC", "Count", "Extended", "Data (hex)"]
+    [
+        "Interface",
+        "ID",
+        "Type",
+        "DLC",
+        "Len",
+        "Flags",
+        "Count",
+        "RTR Count",
+        "First Seen (s)",
+        "Last Seen (s)",
+        "Rate (Hz)",
+        "Min (ms)",
+        "Max (ms)",
+        "Mean (ms)",
+        "Jitter (ms)",
+        "Extended",
+        "Data (hex)",
+    ]
+    .into_iter()
+    .map(Cell::from)
+    .collect::<Row>()
+    .style(header_style)
+}
+
+/// Formats the payload as hex, wrapping onto a new line every
+/// `PAYLOAD_BYTES_PER_LINE` bytes so that 64 byte FD payloads stay readable.
+fn get_payload_text<'a>(data: &[u8]) -> Text<'a> {
+    if data.is_empty() {
+        return Text::from(format!("{:x?}", data));
+    }
+
+    data.chunks(PAYLOAD_BYTES_PER_LINE)
+        .map(|chunk| Line::from(format!("{:x?}", chunk)))
+        .collect::<Vec<Line>>()
+        .into()
+}
+
+/// Style of a payload byte which changed at the given time, fading from red
+/// to yellow as the change gets older until the byte is no longer highlighted
+fn get_byte_change_style(
+    changed_at: Option<Instant>,
+    now: Instant,
+    fade_duration: Duration,
+) -> Style {
+    let Some(changed_at) = changed_at.filter(|_| !fade_duration.is_zero()) else {
+        return Style::default();
+    };
+
+    let age = now.saturating_duration_since(changed_at).as_secs_f64() / fade_duration.as_secs_f64();
+    match age {
+        age if age < 1.0 / 3.0 => Style::default().fg(Color::White).bg(Color::Red),
+        age if age < 2.0 / 3.0 => Style::default().fg(Color::Black).bg(Color::LightRed),
+        age if age < 1.0 => Style::default().fg(Color::Black).bg(Color::Yellow),
+        _ => Style::default(),
+    }
+}
+
+/// Formats the payload like `get_payload_text`, highlighting the bytes which
+/// changed recently
+fn get_highlighted_payload_text<'a>(
+    frame: &CountedFrame,
+    now: Instant,
+    fade_duration: Duration,
+) -> Text<'a> {
+    let data = frame.frame.data();
+    if data.is_empty() {
+        return Text::from(format!("{:x?}", data));
+    }
+
+    data.chunks(PAYLOAD_BYTES_PER_LINE)
+        .enumerate()
+        .map(|(chunk_index, chunk)| {
+            let mut spans = vec![Span::raw("[")];
+            for (i, byte) in chunk.iter().enumerate() {
+                if i > 0 {
+                    spans.push(Span::raw(", "));
+                }
+
+                let changed_at = frame
+                    .byte_changes
+                    .get(chunk_index * PAYLOAD_BYTES_PER_LINE + i)
+                    .copied()
+                    .flatten();
+                spans.push(Span::styled(
+                    format!("{:x}", byte),
+                    get_byte_change_style(changed_at, now, fade_duration),
+                ));
+            }
+            spans.push(Span::raw("]"));
+
+            Line::from(spans)
+        })
+        .collect::<Vec<Line>>()
+        .into()
+}
+
+/// Remote frames carry no payload, so no data is shown for them rather than
+/// an empty payload which would look like a data frame with a DLC of 0
+fn get_frame_data_text<'a>(frame: &CanAnyFrame) -> Text<'a> {
+    match frame {
+        CanAnyFrame::Remote(_) => Text::from("-"),
+        _ => get_payload_text(frame.data()),
+    }
+}
+
+/// Formats a period statistic in milliseconds, or "-" until it is known
+fn format_period(period: Option<f64>) -> String {
+    period.map_or_else(
+        || String::from("-"),
+        |period| format!("{:.3}", period * 1000.0),
+    )
+}
+
+/// Height of a table row holding the given payload
+fn get_row_height(data: &[u8]) -> u16 {
+    data.len().div_ceil(PAYLOAD_BYTES_PER_LINE).max(1) as u16
+}
+
+/// Error frames are drawn with the decoded error classes in place of the payload
+fn get_error_text<'a>(errors: Vec<String>) -> Text<'a> {
+    errors
         .into_iter()
-        .map(Cell::from)
-        .collect::<Row>()
-        .style(header_style)
+        .map(Line::from)
+        .collect::<Vec<Line>>()
+        .into()
 }
 
 fn get_row_for_timestamped_frame<'a>(frame: &TimestampedFrame) -> Vec<Cell<'a>> {
+    if let CanAnyFrame::Error(error_frame) = &frame.frame {
+        return get_row_for_error_frame(frame, decode_error_frame(error_frame));
+    }
+
     let mut cells = vec![];
     cells.push(Cell::from(Text::from(format!("{}", frame.frame_number))));
-    cells.push(Cell::from(Text::from(format!("{}", frame.get_timestamp()))));
+    cells.push(Cell::from(Text::from(format_timestamp(
+        frame.get_timestamp(),
+    ))));
+    cells.push(Cell::from(Text::from(
+        frame
+            .get_hw_timestamp()
+            .map_or_else(|| String::from("-"), format_timestamp),
+    )));
+    cells.push(Cell::from(Text::from(frame.interface.to_string())));
     cells.push(Cell::from(Text::from(format!(
         "0x{:x}",
         frame.get_numeric_id()
     ))));
+    cells.push(Cell::from(Text::from(frame.frame_type.to_string())));
+    cells.push(Cell::from(Text::from(format!("{}", frame.frame.dlc()))));
     cells.push(Cell::from(Text::from(format!(
-        "{:?}",
-        CanFrame::dlc(&frame.frame)
+        "{}",
+        get_data_len(&frame.frame)
     ))));
+    cells.push(Cell::from(Text::from(get_fd_flags(&frame.frame))));
     cells.push(Cell::from(Text::from(format!(
         "{}",
-        CanFrame::is_extended(&frame.frame)
+        frame.frame.is_extended()
     ))));
-    cells.push(Cell::from(Text::from(format!(
-        "{:x?}",
-        CanFrame::data(&frame.frame)
+    cells.push(Cell::from(get_frame_data_text(&frame.frame)));
+
+    cells
+}
+
+fn get_row_for_error_frame<'a>(frame: &TimestampedFrame, errors: Vec<String>) -> Vec<Cell<'a>> {
+    let mut cells = vec![];
+    cells.push(Cell::from(Text::from(format!("{}", frame.frame_number))));
+    cells.push(Cell::from(Text::from(format_timestamp(
+        frame.get_timestamp(),
     ))));
+    cells.push(Cell::from(Text::from(
+        frame
+            .get_hw_timestamp()
+            .map_or_else(|| String::from("-"), format_timestamp),
+    )));
+    cells.push(Cell::from(Text::from(frame.interface.to_string())));
+    cells.push(Cell::from(Text::from("-")));
+    cells.push(Cell::from(Text::from(frame.frame_type.to_string())));
+    cells.push(Cell::from(Text::from("-")));
+    cells.push(Cell::from(Text::from("-")));
+    cells.push(Cell::from(Text::from("-")));
+    cells.push(Cell::from(Text::from("-")));
+    cells.push(Cell::from(get_error_text(errors)));
 
     cells
 }
 
-fn get_row_for_counted_frame_set<'a>(frame: &CountedFrame) -> Vec<Cell<'a>> {
+fn get_timestamped_frame_row_height(frame: &TimestampedFrame) -> u16 {
+    match &frame.frame {
+        CanAnyFrame::Error(error_frame) => decode_error_frame(error_frame).len() as u16,
+        _ => get_row_height(frame.frame.data()),
+    }
+}
+
+/// Row for a frame in the frame list, with error frames and the frame marked
+/// for diffing standing out from the alternating colors of regular frames
+fn get_timestamped_frame_row<'a>(
+    frame: &TimestampedFrame,
+    color: Color,
+    is_marked: bool,
+) -> Row<'a> {
+    let style = match &frame.frame {
+        _ if is_marked => Style::default().fg(Color::Black).bg(Color::LightBlue),
+        CanAnyFrame::Error(_) => Style::default().fg(Color::White).bg(Color::Red),
+        _ => Style::default().fg(Color::Black).bg(color),
+    };
+
+    Row::new(get_row_for_timestamped_frame(frame))
+        .height(get_timestamped_frame_row_height(frame))
+        .style(style)
+}
+
+fn get_row_for_counted_frame_set<'a>(
+    frame: &CountedFrame,
+    now: Instant,
+    change_highlight_duration: Duration,
+) -> Vec<Cell<'a>> {
     let mut cells = vec![];
+    cells.push(Cell::from(Text::from(frame.interface.to_string())));
     cells.push(Cell::from(Text::from(format!(
         "0x{:x}",
         socketcan::Frame::raw_id(&frame.frame)
     ))));
+    cells.push(Cell::from(Text::from(frame.frame_type.to_string())));
     cells.push(Cell::from(Text::from(format!("{}", frame.frame.dlc()))));
+    cells.push(Cell::from(Text::from(format!(
+        "{}",
+        get_data_len(&frame.frame)
+    ))));
+    cells.push(Cell::from(Text::from(get_fd_flags(&frame.frame))));
     cells.push(Cell::from(Text::from(format!("{}", frame.capture_count))));
+    cells.push(Cell::from(Text::from(format!(
+        "{}",
+        frame.remote_request_count
+    ))));
+    for seen in [frame.first_seen, frame.last_seen] {
+        cells.push(Cell::from(Text::from(
+            seen.map_or_else(|| String::from("-"), format_timestamp),
+        )));
+    }
+
+    let period_statistics = &frame.period_statistics;
+    cells.push(Cell::from(Text::from(
+        period_statistics
+            .get_rate()
+            .map_or_else(|| String::from("-"), |rate| format!("{:.1}", rate)),
+    )));
+    cells.push(Cell::from(Text::from(format_period(
+        period_statistics.get_min(),
+    ))));
+    cells.push(Cell::from(Text::from(format_period(
+        period_statistics.get_max(),
+    ))));
+    cells.push(Cell::from(Text::from(format_period(
+        period_statistics.get_mean(),
+    ))));
+    cells.push(Cell::from(Text::from(format_period(
+        period_statistics.get_jitter(),
+    ))));
     cells.push(Cell::from(Text::from(format!(
         "{}",
         frame.frame.is_extended()
     ))));
-    cells.push(Cell::from(Text::from(format!("{:x?}", frame.frame.data()))));
+    cells.push(Cell::from(match frame.frame {
+        CanAnyFrame::Remote(_) => Text::from("-"),
+        _ => get_highlighted_payload_text(frame, now, change_highlight_duration),
+    }));
 
     cells
 }
@@ -87,11 +321,16 @@ fn draw_timestamped_frames(
     let table = Table::new(
         rows,
         [
+            Constraint::Percentage(8),
+            Constraint::Percentage(14),
+            Constraint::Percentage(12),
+            Constraint::Percentage(8),
             Constraint::Percentage(10),
+            Constraint::Percentage(6),
+            Constraint::Percentage(5),
+            Constraint::Percentage(5),
             Constraint::Percentage(10),
-            Constraint::Percentage(10),
-            Constraint::Percentage(10),
-            Constraint::Percentage(10),
+            Constraint::Percentage(8),
             Constraint::Percentage(100),
         ],
     )
@@ -111,10 +350,22 @@ fn draw_frame_set(
     let table = Table::new(
         rows,
         [
+            Constraint::Percentage(6),
+            Constraint::Percentage(6),
+            Constraint::Percentage(4),
+            Constraint::Percentage(3),
+            Constraint::Percentage(3),
+            Constraint::Percentage(6),
+            Constraint::Percentage(5),
+            Constraint::Percentage(5),
             Constraint::Percentage(10),
             Constraint::Percentage(10),
-            Constraint::Percentage(10),
-            Constraint::Percentage(10),
+            Constraint::Percentage(5),
+            Constraint::Percentage(5),
+            Constraint::Percentage(5),
+            Constraint::Percentage(5),
+            Constraint::Percentage(5),
+            Constraint::Percentage(5),
             Constraint::Percentage(100),
         ],
     )
@@ -123,26 +374,54 @@ fn draw_frame_set(
     f.render_stateful_widget(table, area, &mut app.table_state);
 }
 
+/// Points the table at the row of the selected frame. Rows added above the
+/// selected frame scroll the table along, so that the selected frame keeps its
+/// place on screen while it is being read.
+fn update_table_selection(app: &mut App, selected_row: Option<usize>) {
+    match (app.table_state.selected(), selected_row) {
+        (Some(previous_row), Some(row)) => {
+            let offset = app.table_state.offset_mut();
+            *offset = (*offset + row).saturating_sub(previous_row);
+        }
+        (_, None) => *app.table_state.offset_mut() = 0,
+        (None, Some(_)) => {}
+    }
+
+    app.table_state.select(selected_row);
+}
+
 pub fn draw_counted_frame_set(f: &mut ratatui::Frame, app: &mut App, area: Rect) {
     let header_style = Style::default().fg(Color::White).bg(Color::Black);
     let selected_style = Style::default().fg(Color::Black).bg(Color::LightYellow);
 
-    let mut rows: Vec<Row> = Vec::new();
-
-    let captured_frames = &app.frame_captor.get_captured_frames();
-
-    let frame_set = &captured_frames.lock().unwrap().captured_frames_set;
+    let now = app.get_display_time();
+    let rows = app
+        .get_displayed_frame_set()
+        .into_iter()
+        .enumerate()
+        .map(|(i, (_, frame))| {
+            let color = match i % 2 {
+                0 => app.row_color_main,
+                _ => app.row_color_alt,
+            };
 
-    frame_set.values().enumerate().for_each(|(i, frame)| {
-        let color = match i % 2 {
-            0 => app.row_color_main,
-            _ => app.row_color_alt,
-        };
+            // IDs which stopped arriving are flagged as stale
+            let style = match frame.missing_since {
+                Some(_) => Style::default().fg(Color::White).bg(Color::DarkGray),
+                None => Style::default().fg(Color::Black).bg(color),
+            };
 
-        let cells = get_row_for_counted_frame_set(frame);
-        rows.push(Row::new(cells).style(Style::default().fg(Color::Black).bg(color)))
-    });
+            Row::new(get_row_for_counted_frame_set(
+                frame,
+                now,
+                app.change_highlight_duration,
+            ))
+            .height(get_row_height(frame.frame.data()))
+            .style(style)
+        })
+        .collect::<Vec<Row>>();
 
+    update_table_selection(app, app.get_selected_row());
     draw_frame_set(rows, header_style, selected_style, f, area, app);
 }
 
@@ -150,43 +429,48 @@ pub fn draw_timestamped_frame_table(f: &mut ratatui::Frame, app: &mut App, area:
     let header_style = Style::default().fg(Color::White).bg(Color::Black);
     let selected_style = Style::default().fg(Color::Black).bg(Color::LightYellow);
 
-    let mut rows: Vec<Row> = Vec::new();
-
-    let captured_frames = &app.frame_captor.get_captured_frames();
-    let frame_vec = &captured_frames.lock().unwrap().captured_frames_list;
+    // Only the frames which fit in the table are drawn, below its header
+    let table_height = area.height.saturating_sub(1) as usize;
+    let mut frames = app.get_displayed_frames(table_height);
+    let frame_count = frames.len();
 
-    if let Some(filter) = &app.frame_id_filter {
-        frame_vec
+    let mut rows_height = 0;
+    frames.truncate(
+        frames
             .iter()
-            .rev()
-            .filter(|f| (filter.filter_callback)(f, &filter.ids))
-            .take(app.frames_displayed_max)
-            .enumerate()
-            .for_each(|(i, frame)| {
-                let color = match i % 2 {
-                    0 => app.row_color_main,
-                    _ => app.row_color_alt,
-                };
-
-                let cells = get_row_for_timestamped_frame(frame);
-                rows.push(Row::new(cells).style(Style::default().fg(Color::Black).bg(color)));
-            });
-    } else {
-        frame_vec
-            .iter()
-            .rev()
-            .take(app.frames_displayed_max)
-            .enumerate()
-            .for_each(|(i, frame)| {
-                let color = match i % 2 {
-                    0 => app.row_color_main,
-                    _ => app.row_color_alt,
-                };
-
-                let cells = get_row_for_timestamped_frame(frame);
-                rows.push(Row::new(cells).style(Style::default().fg(Color::Black).bg(color)));
-            });
-    }
+            .take_while(|frame| {
+                rows_height += get_timestamped_frame_row_height(frame) as usize;
+                rows_height <= table_height
+            })
+            .count(),
+    );
+
+    // A page holds fewer frames than the table has lines when some of them
+    // take up several lines
+    let page_size = match frames.len() < frame_count {
+        true => frames.len(),
+        false => table_height,
+    };
+
+    let rows = frames
+        .into_iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            let color = match i % 2 {
+                0 => app.row_color_main,
+                _ => app.row_color_alt,
+            };
+
+            let is_marked = app
+                .marked_frame
+                .as_ref()
+                .is_some_and(|marked_frame| marked_frame.frame_number == frame.frame_number);
+
+            get_timestamped_frame_row(frame, color, is_marked)
+        })
+        .collect::<Vec<Row>>();
 
+    app.frame_table_page_size = page_size.max(1);
+    app.table_state = TableState::default().with_selected(app.get_selected_row());
     draw_timestamped_frames(rows, header_style, selected_style, f, area, app);
 }
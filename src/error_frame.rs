@@ -0,0 +1,218 @@
+use embedded_can::Frame;
+use socketcan::errors::{ControllerProblem, Location, ViolationType};
+use socketcan::CanErrorFrame;
+
+// Error class bits of the error frame's CAN ID, see linux/can/error.h
+const CAN_ERR_TX_TIMEOUT: u32 = 0x0001;
+const CAN_ERR_LOSTARB: u32 = 0x0002;
+const CAN_ERR_CRTL: u32 = 0x0004;
+const CAN_ERR_PROT: u32 = 0x0008;
+const CAN_ERR_TRX: u32 = 0x0010;
+const CAN_ERR_ACK: u32 = 0x0020;
+const CAN_ERR_BUSOFF: u32 = 0x0040;
+const CAN_ERR_BUSERROR: u32 = 0x0080;
+const CAN_ERR_RESTARTED: u32 = 0x0100;
+const CAN_ERR_CNT: u32 = 0x0200;
+
+/// Whether the error frame reports a change of the controller's error state,
+/// such as going error-passive, bus-off or being restarted, rather than an
+/// error on the bus
+pub fn is_controller_state_change(frame: &CanErrorFrame) -> bool {
+    frame.error_bits() & (CAN_ERR_CRTL | CAN_ERR_BUSOFF | CAN_ERR_RESTARTED) != 0
+}
+
+/// Decodes an error frame into human readable error classes. The kernel may
+/// set several error class bits, and several bits within each detail byte,
+/// in a single error frame, so every set bit is decoded.
+pub fn decode_error_frame(frame: &CanErrorFrame) -> Vec<String> {
+    let error_bits = frame.error_bits();
+    let data = frame.data();
+    let data_byte = |i: usize| data.get(i).copied().unwrap_or(0);
+
+    let mut errors = vec![];
+
+    if error_bits & CAN_ERR_TX_TIMEOUT != 0 {
+        errors.push(String::from("TX timeout"));
+    }
+    if error_bits & CAN_ERR_LOSTARB != 0 {
+        match data_byte(0) {
+            0 => errors.push(String::from("arbitration lost")),
+            bit => errors.push(format!("arbitration lost at bit {}", bit)),
+        }
+    }
+    if error_bits & CAN_ERR_CRTL != 0 {
+        let problem = decode_bits(data_byte(1), |bit| {
+            ControllerProblem::try_from(bit).map(|p| p.to_string()).ok()
+        });
+        errors.push(format!("controller: {}", problem));
+    }
+    if error_bits & CAN_ERR_PROT != 0 {
+        let violation = decode_bits(data_byte(2), |bit| {
+            ViolationType::try_from(bit).map(|v| v.to_string()).ok()
+        });
+        let location = Location::try_from(data_byte(3))
+            .map(|location| location.to_string())
+            .unwrap_or_else(|_| format!("location 0x{:02x}", data_byte(3)));
+        errors.push(format!("protocol violation at {}: {}", location, violation));
+    }
+    if error_bits & CAN_ERR_TRX != 0 {
+        errors.push(format!("transceiver: {}", decode_transceiver(data_byte(4))));
+    }
+    if error_bits & CAN_ERR_ACK != 0 {
+        errors.push(String::from("no ACK"));
+    }
+    if error_bits & CAN_ERR_BUSOFF != 0 {
+        errors.push(String::from("BUS OFF"));
+    }
+    if error_bits & CAN_ERR_BUSERROR != 0 {
+        errors.push(String::from("bus error"));
+    }
+    if error_bits & CAN_ERR_RESTARTED != 0 {
+        errors.push(String::from("controller restarted"));
+    }
+    if error_bits & CAN_ERR_CNT != 0 {
+        errors.push(format!(
+            "TX errors: {}, RX errors: {}",
+            data_byte(6),
+            data_byte(7)
+        ));
+    }
+
+    if errors.is_empty() {
+        errors.push(format!("unknown error (0x{:x})", error_bits));
+    }
+
+    errors
+}
+
+/// Decodes every set bit of a detail byte, or reports it as unspecified if
+/// no bit is set
+fn decode_bits(byte: u8, decode_bit: impl Fn(u8) -> Option<String>) -> String {
+    if byte == 0 {
+        return String::from("unspecified");
+    }
+
+    (0..8)
+        .map(|i| 1u8 << i)
+        .filter(|bit| byte & bit != 0)
+        .map(|bit| decode_bit(bit).unwrap_or_else(|| format!("0x{:02x}", bit)))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Decodes the transceiver status of `data[4]`, where the high nibble
+/// describes CAN low and the low nibble CAN high
+fn decode_transceiver(byte: u8) -> String {
+    let can_high = match byte & 0x0f {
+        0x00 => None,
+        0x04 => Some("CAN-H no wire"),
+        0x05 => Some("CAN-H short to BAT"),
+        0x06 => Some("CAN-H short to VCC"),
+        0x07 => Some("CAN-H short to GND"),
+        _ => Some("CAN-H unknown fault"),
+    };
+    let can_low = match byte & 0xf0 {
+        0x00 => None,
+        0x40 => Some("CAN-L no wire"),
+        0x50 => Some("CAN-L short to BAT"),
+        0x60 => Some("CAN-L short to VCC"),
+        0x70 => Some("CAN-L short to GND"),
+        0x80 => Some("CAN-L short to CAN-H"),
+        _ => Some("CAN-L unknown fault"),
+    };
+
+    let faults = [can_high, can_low]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    if faults.is_empty() {
+        return String::from("unspecified");
+    }
+
+    faults.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(error_bits: u32, data: &[u8]) -> Vec<String> {
+        decode_error_frame(&CanErrorFrame::new_error(error_bits, data).unwrap())
+    }
+
+    #[test]
+    fn decodes_error_frames() {
+        for (error_bits, data, errors) in [
+            (CAN_ERR_LOSTARB, &[5][..], &["arbitration lost at bit 5"][..]),
+            (CAN_ERR_LOSTARB, &[0], &["arbitration lost"]),
+            (
+                CAN_ERR_PROT | CAN_ERR_BUSERROR,
+                &[0, 0, 0x08, 0x0a],
+                &[
+                    "protocol violation at data section: unable to send dominant bit",
+                    "bus error",
+                ],
+            ),
+            (
+                CAN_ERR_PROT,
+                &[0, 0, 0x11, 0x19],
+                &["protocol violation at ACK slot: single bit error, unable to send recessive bit"],
+            ),
+            (
+                CAN_ERR_PROT,
+                &[0, 0, 0, 0x01],
+                &["protocol violation at location 0x01: unspecified"],
+            ),
+            (
+                CAN_ERR_CRTL,
+                &[0, 0x14],
+                &["controller: ERROR WARNING (receive), ERROR PASSIVE (receive)"],
+            ),
+            (
+                CAN_ERR_TRX,
+                &[0, 0, 0, 0, 0x74],
+                &["transceiver: CAN-H no wire, CAN-L short to GND"],
+            ),
+            (
+                CAN_ERR_TRX,
+                &[0, 0, 0, 0, 0x80],
+                &["transceiver: CAN-L short to CAN-H"],
+            ),
+            (CAN_ERR_TRX, &[], &["transceiver: unspecified"]),
+            (
+                CAN_ERR_CNT | CAN_ERR_BUSOFF,
+                &[0, 0, 0, 0, 0, 0, 96, 128],
+                &["BUS OFF", "TX errors: 96, RX errors: 128"],
+            ),
+            (
+                CAN_ERR_TX_TIMEOUT | CAN_ERR_ACK | CAN_ERR_RESTARTED,
+                &[],
+                &["TX timeout", "no ACK", "controller restarted"],
+            ),
+            (0, &[], &["unknown error (0x0)"]),
+        ] {
+            assert_eq!(decode(error_bits, data), errors, "{:#x} {:?}", error_bits, data);
+        }
+    }
+
+    #[test]
+    fn tells_controller_state_changes_from_bus_errors() {
+        for (error_bits, is_state_change) in [
+            (CAN_ERR_CRTL, true),
+            (CAN_ERR_BUSOFF, true),
+            (CAN_ERR_RESTARTED, true),
+            (CAN_ERR_PROT | CAN_ERR_BUSERROR | CAN_ERR_CRTL, true),
+            (CAN_ERR_PROT | CAN_ERR_BUSERROR, false),
+            (CAN_ERR_ACK, false),
+            (CAN_ERR_LOSTARB, false),
+        ] {
+            let frame = CanErrorFrame::new_error(error_bits, &[]).unwrap();
+            assert_eq!(
+                is_controller_state_change(&frame),
+                is_state_change,
+                "{:#x}",
+                error_bits
+            );
+        }
+    }
+}
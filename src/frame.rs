@@ -1,70 +1,420 @@
-use anyhow::Result;
-use embedded_can::nb::Can;
 use embedded_can::Frame;
-use socketcan::{CanFrame, CanSocket, Socket};
+use socketcan::{CanAnyFrame, CanTimestamps};
 
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use crate::baseline::ObservedBits;
+use crate::error_frame::is_controller_state_change;
+use crate::frame_source::{
+    is_interface_lost, FrameSource, InterfaceState, ReceivedFrame, RECEIVE_TIMEOUT,
+};
+use crate::message_timeout::{MessageTimeoutDetector, MessageTimeouts, TimeoutEvent};
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The kind of a captured frame
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    Data,
+    Remote,
+    Error,
+}
+
+impl FrameType {
+    pub fn of(frame: &CanAnyFrame) -> Self {
+        match frame {
+            CanAnyFrame::Normal(_) | CanAnyFrame::Fd(_) => FrameType::Data,
+            CanAnyFrame::Remote(_) => FrameType::Remote,
+            CanAnyFrame::Error(_) => FrameType::Error,
+        }
+    }
+}
+
+impl std::fmt::Display for FrameType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameType::Data => write!(f, "Data"),
+            FrameType::Remote => write!(f, "RTR"),
+            FrameType::Error => write!(f, "Error"),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct TimestampedFrame {
-    pub frame: CanFrame,
+    pub frame: CanAnyFrame,
+    pub frame_type: FrameType,
+    pub interface: Arc<str>,
     timestamp: SystemTime,
+    hw_timestamp: Option<Duration>,
     pub frame_number: u64,
+    /// Frames dropped by the kernel on this interface since the previous frame
+    pub dropped_frames_before: u32,
 }
 
 impl TimestampedFrame {
-    pub fn new(frame: CanFrame, frame_number: u64) -> Self {
+    pub fn new(
+        frame: CanAnyFrame,
+        interface: Arc<str>,
+        frame_number: u64,
+        timestamps: CanTimestamps,
+    ) -> Self {
         Self {
             frame,
-            timestamp: SystemTime::now(),
+            frame_type: FrameType::of(&frame),
+            interface,
+            timestamp: get_receive_time(&timestamps),
+            hw_timestamp: timestamps.hw,
             frame_number,
+            dropped_frames_before: 0,
         }
     }
 
-    pub fn get_timestamp(&self) -> u128 {
+    /// Time at which the kernel received the frame, since the unix epoch
+    pub fn get_timestamp(&self) -> Duration {
         self.timestamp
             .duration_since(UNIX_EPOCH)
             .expect("Error unwrapping duration since epoch!")
-            .as_millis()
+    }
+
+    /// Raw hardware clock value of the adapter when it received the frame,
+    /// if the adapter supports hardware timestamps. This is not a wall-clock time.
+    pub fn get_hw_timestamp(&self) -> Option<Duration> {
+        self.hw_timestamp
     }
 
     pub fn get_numeric_id(&self) -> u32 {
-        match CanFrame::id(&self.frame) {
-            socketcan::Id::Standard(standard_id) => standard_id.as_raw() as u32,
-            socketcan::Id::Extended(extended_id) => extended_id.as_raw(),
+        get_raw_id(self.frame.id())
+    }
+}
+
+/// Numeric value of a standard or extended CAN ID
+pub fn get_raw_id(id: embedded_can::Id) -> u32 {
+    match id {
+        embedded_can::Id::Standard(standard_id) => standard_id.as_raw() as u32,
+        embedded_can::Id::Extended(extended_id) => extended_id.as_raw(),
+    }
+}
+
+/// Wall-clock receive time of a frame. The network stack timestamp is taken
+/// closest to the driver and is preferred over the socket layer timestamp,
+/// falling back to the current time if the kernel delivered neither.
+fn get_receive_time(timestamps: &CanTimestamps) -> SystemTime {
+    timestamps
+        .sw
+        .or(timestamps.socket)
+        .unwrap_or_else(SystemTime::now)
+}
+
+/// Formats a timestamp as seconds with microsecond precision
+pub fn format_timestamp(timestamp: Duration) -> String {
+    format!("{}.{:06}", timestamp.as_secs(), timestamp.subsec_micros())
+}
+
+/// Number of payload bytes carried by the frame. For classic frames this is
+/// the same as the DLC, for FD frames the DLC is an encoded length, and
+/// remote frames carry no payload, their DLC being the requested length.
+pub fn get_data_len(frame: &CanAnyFrame) -> usize {
+    match frame {
+        CanAnyFrame::Remote(_) => 0,
+        // The length of an FD frame is only known from its payload, as the
+        // generic frame length is its DLC
+        _ => frame.data().len(),
+    }
+}
+
+/// Human readable FD flags of the frame, or "-" for classic frames
+pub fn get_fd_flags(frame: &CanAnyFrame) -> String {
+    match frame {
+        CanAnyFrame::Fd(fd_frame) => {
+            let mut flags = String::from("FD");
+            if fd_frame.is_brs() {
+                flags.push_str(" BRS");
+            }
+            if fd_frame.is_esi() {
+                flags.push_str(" ESI");
+            }
+            flags
         }
+        _ => String::from("-"),
     }
 }
 
+/// Weight of the latest period in the moving average the current rate is
+/// derived from, so that the rate follows changes within a few frames
+const PERIOD_MOVING_AVERAGE_WEIGHT: f64 = 0.1;
+
+/// Statistics of the time between consecutive data frames of an ID, in seconds
+#[derive(Clone, Default)]
+pub struct PeriodStatistics {
+    count: u64,
+    min: f64,
+    max: f64,
+    mean: f64,
+    /// Sum of the squared differences from the mean, see Welford's algorithm
+    sum_squared_diffs: f64,
+    moving_average: f64,
+}
+
+impl PeriodStatistics {
+    fn add(&mut self, period: f64) {
+        self.count += 1;
+
+        if self.count == 1 {
+            self.min = period;
+            self.max = period;
+            self.moving_average = period;
+        } else {
+            self.min = self.min.min(period);
+            self.max = self.max.max(period);
+            self.moving_average += PERIOD_MOVING_AVERAGE_WEIGHT * (period - self.moving_average);
+        }
+
+        let diff = period - self.mean;
+        self.mean += diff / self.count as f64;
+        self.sum_squared_diffs += diff * (period - self.mean);
+    }
+
+    /// The statistic, or `None` until a period has been measured
+    fn get(&self, statistic: f64) -> Option<f64> {
+        (self.count > 0).then_some(statistic)
+    }
+
+    pub fn get_min(&self) -> Option<f64> {
+        self.get(self.min)
+    }
+
+    pub fn get_max(&self) -> Option<f64> {
+        self.get(self.max)
+    }
+
+    pub fn get_mean(&self) -> Option<f64> {
+        self.get(self.mean)
+    }
+
+    /// Standard deviation of the period
+    pub fn get_jitter(&self) -> Option<f64> {
+        self.get((self.sum_squared_diffs / self.count as f64).sqrt())
+    }
+
+    /// Number of periods measured
+    pub fn get_count(&self) -> u64 {
+        self.count
+    }
+
+    /// Moving average of the period, which follows changes in the rate
+    /// rather than averaging over the whole capture
+    pub fn get_current_period(&self) -> Option<f64> {
+        self.get(self.moving_average)
+    }
+
+    /// Frames per second at the current rate
+    pub fn get_rate(&self) -> Option<f64> {
+        self.get_current_period()
+            .filter(|period| *period > 0.0)
+            .map(|period| 1.0 / period)
+    }
+}
+
+/// How a bit of the payload of an ID has changed between its frames
+#[derive(Clone, Copy, Default)]
+pub struct BitChanges {
+    pub toggle_count: u32,
+    /// When the bit last toggled, if it ever did
+    pub changed_at: Option<Instant>,
+}
+
+/// The latest frame captured for an ID. Remote requests for the ID are counted
+/// separately from the data frames, and only replace the latest frame until
+/// the first data frame has been captured.
+#[derive(Clone)]
 pub struct CountedFrame {
-    pub frame: CanFrame,
+    pub frame: CanAnyFrame,
+    pub frame_type: FrameType,
+    pub interface: Arc<str>,
     pub capture_count: usize,
+    pub remote_request_count: usize,
+    /// Timestamps of the first and latest data frames of the ID
+    pub first_seen: Option<Duration>,
+    pub last_seen: Option<Duration>,
+    pub period_statistics: PeriodStatistics,
+    /// Timestamp of the latest frame before the ID timed out, while no frame
+    /// has been received since
+    pub missing_since: Option<Duration>,
+    /// When each payload byte last changed value, or `None` for bytes which
+    /// kept their value since the first data frame of the ID
+    pub byte_changes: Vec<Option<Instant>>,
+    /// Changes of each bit of the payload, eight per byte with the least
+    /// significant bit of each byte first
+    pub bit_changes: Vec<BitChanges>,
+    /// Values seen for each bit of the payloads of the data frames
+    pub observed_bits: ObservedBits,
 }
 
 impl CountedFrame {
-    pub fn new(frame: CanFrame) -> Self {
+    pub fn new(frame: CanAnyFrame, interface: Arc<str>) -> Self {
         Self {
             frame,
+            frame_type: FrameType::of(&frame),
+            interface,
             capture_count: 0,
+            remote_request_count: 0,
+            first_seen: None,
+            last_seen: None,
+            period_statistics: PeriodStatistics::default(),
+            missing_since: None,
+            byte_changes: vec![],
+            bit_changes: vec![],
+            observed_bits: ObservedBits::default(),
         }
     }
 
-    pub fn with_capture_count(frame: CanFrame, capture_count: usize) -> Self {
-        Self {
-            frame,
-            capture_count,
+    fn update(&mut self, frame: CanAnyFrame, timestamp: Duration) {
+        match FrameType::of(&frame) {
+            FrameType::Remote => {
+                self.remote_request_count += 1;
+                if self.capture_count > 0 {
+                    return;
+                }
+            }
+            _ => {
+                if self.capture_count > 0 {
+                    self.update_payload_changes(frame.data());
+                }
+                self.capture_count += 1;
+                self.observed_bits.observe(frame.data());
+                self.update_timing(timestamp);
+            }
         }
+
+        self.frame = frame;
+        self.frame_type = FrameType::of(&frame);
+    }
+
+    /// Notes which bytes and bits of the payload differ from the previous
+    /// payload of the ID. Bytes beyond the end of the previous payload count
+    /// as changed, but none of their bits as toggled.
+    fn update_payload_changes(&mut self, data: &[u8]) {
+        let previous_data = self.frame.data();
+        let now = Instant::now();
+
+        self.byte_changes.resize(data.len(), None);
+        self.bit_changes
+            .resize(data.len() * 8, BitChanges::default());
+
+        for (i, byte) in data.iter().enumerate() {
+            let toggled_bits = match previous_data.get(i) {
+                Some(previous_byte) => byte ^ previous_byte,
+                None => {
+                    self.byte_changes[i] = Some(now);
+                    continue;
+                }
+            };
+            if toggled_bits == 0 {
+                continue;
+            }
+
+            self.byte_changes[i] = Some(now);
+            for bit in (0..8).filter(|bit| toggled_bits & (1 << bit) != 0) {
+                let bit_changes = &mut self.bit_changes[i * 8 + bit];
+                bit_changes.toggle_count += 1;
+                bit_changes.changed_at = Some(now);
+            }
+        }
+    }
+
+    /// Frames from several sources may arrive slightly out of order, in which
+    /// case no period is measured for the frame
+    fn update_timing(&mut self, timestamp: Duration) {
+        if let Some(period) = self
+            .last_seen
+            .and_then(|last_seen| timestamp.checked_sub(last_seen))
+        {
+            self.period_statistics.add(period.as_secs_f64());
+        }
+
+        self.first_seen.get_or_insert(timestamp);
+        self.last_seen = Some(
+            self.last_seen
+                .map_or(timestamp, |last_seen| last_seen.max(timestamp)),
+        );
+    }
+}
+
+/// How long the frames per second history reaches back, which is as far as
+/// the frames per second chart shows
+pub const FRAMES_PER_SECOND_HISTORY_SECONDS: u64 = 300;
+
+/// Limits the number of frames kept in the frame list, evicting the oldest
+/// frames first once the limit is reached
+pub struct FrameHistoryLimit {
+    pub max_frames: usize,
+    /// Evicted frames are written to this file in candump log format, rather
+    /// than being discarded
+    pub spill_file: Option<File>,
+}
+
+impl FrameHistoryLimit {
+    /// The number of frames which fit in the given number of megabytes
+    pub fn max_frames_for_megabytes(megabytes: usize) -> usize {
+        megabytes * 1024 * 1024 / std::mem::size_of::<TimestampedFrame>()
     }
 }
 
+/// How often the capture threads check the state of their interfaces while
+/// frames are coming in, and while waiting for a lost interface to return
+const INTERFACE_STATE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the capture threads first wait after a source failed to receive
+/// again, which doubles with every further error in a row
+const RECEIVE_ERROR_BACKOFF: Duration = Duration::from_millis(10);
+
+/// What a capture thread hands over to the frame state
+pub enum CaptureEvent {
+    Frame(ReceivedFrame),
+    /// The state of an interface changed, or was checked for the first time
+    InterfaceState(Arc<str>, InterfaceState),
+    /// A source failed to receive, described along with its interfaces
+    ReceiveError(String),
+}
+
+/// Frame count and state of an interface, as shown in the header
+pub struct InterfaceSummary {
+    pub interface: Arc<str>,
+    pub frame_count: usize,
+    /// Only known for interfaces whose source can tell their state
+    pub state: Option<InterfaceState>,
+}
+
+/// Frames in the frame set are unique per interface and CAN ID, as the same
+/// ID may carry unrelated messages on different buses. A standard and an
+/// extended ID with the same numeric value are different IDs.
+pub type FrameSetKey = (Arc<str>, embedded_can::Id);
+
 pub struct CapturedFrameState {
-    pub captured_frames_list: Vec<TimestampedFrame>,
-    pub captured_frames_set: HashMap<u32, CountedFrame>,
+    pub captured_frames_list: VecDeque<TimestampedFrame>,
+    pub captured_frames_set: HashMap<FrameSetKey, CountedFrame>,
+    interface_frame_counts: BTreeMap<Arc<str>, usize>,
+    interface_states: BTreeMap<Arc<str>, InterfaceState>,
     total_frame_count: usize,
+    error_frame_count: usize,
+    dropped_frame_count: usize,
+    receive_error_count: usize,
+    last_receive_error: Option<String>,
+    next_frame_number: u64,
+    max_frames: usize,
+    evicted_frame_count: usize,
+    spill_writer: Option<BufWriter<File>>,
     frames_per_second: usize,
-    frames_per_second_history: Vec<(f64, SystemTime)>,
+    frames_per_second_history: VecDeque<(f64, SystemTime)>,
+    running_second_timestamp: Instant,
+    tot_frames_as_of_last_second: usize,
+    message_timeout_detector: Option<MessageTimeoutDetector>,
 }
 
 impl Default for CapturedFrameState {
@@ -76,41 +426,240 @@ impl Default for CapturedFrameState {
 impl CapturedFrameState {
     pub fn new() -> Self {
         Self {
-            captured_frames_list: vec![],
+            captured_frames_list: VecDeque::new(),
             captured_frames_set: HashMap::new(),
+            interface_frame_counts: BTreeMap::new(),
+            interface_states: BTreeMap::new(),
             total_frame_count: 0,
+            error_frame_count: 0,
+            dropped_frame_count: 0,
+            receive_error_count: 0,
+            last_receive_error: None,
+            next_frame_number: 0,
+            max_frames: usize::MAX,
+            evicted_frame_count: 0,
+            spill_writer: None,
             frames_per_second: 0,
-            frames_per_second_history: vec![],
+            frames_per_second_history: VecDeque::new(),
+            running_second_timestamp: Instant::now(),
+            tot_frames_as_of_last_second: 0,
+            message_timeout_detector: None,
+        }
+    }
+
+    /// Keeps at most `max_frames` frames in the frame list, see
+    /// `FrameHistoryLimit`
+    pub fn with_history_limit(history_limit: FrameHistoryLimit) -> Self {
+        Self {
+            max_frames: history_limit.max_frames,
+            spill_writer: history_limit.spill_file.map(BufWriter::new),
+            ..Self::new()
+        }
+    }
+
+    /// Detects IDs which stop arriving, see `MessageTimeouts`
+    pub fn with_message_timeouts(self, message_timeouts: MessageTimeouts) -> Self {
+        Self {
+            message_timeout_detector: Some(MessageTimeoutDetector::new(message_timeouts)),
+            ..self
         }
     }
 
+    /// Makes the interface show up in the per-interface counters before any
+    /// frames have been captured on it
+    fn register_interface(&mut self, interface: Arc<str>) {
+        self.interface_frame_counts.entry(interface).or_insert(0);
+    }
+
     pub fn clear_captured_frames(&mut self) {
         self.captured_frames_list.clear();
         self.captured_frames_set.clear();
+        self.interface_frame_counts
+            .values_mut()
+            .for_each(|count| *count = 0);
         self.total_frame_count = 0;
+        self.error_frame_count = 0;
+        self.dropped_frame_count = 0;
+        self.receive_error_count = 0;
+        self.last_receive_error = None;
+        self.evicted_frame_count = 0;
+        self.tot_frames_as_of_last_second = 0;
         self.frames_per_second = 0;
+        if let Some(message_timeout_detector) = &mut self.message_timeout_detector {
+            message_timeout_detector.clear();
+        }
     }
 
-    fn process_frame(&mut self, rx_frame: CanFrame, frame_number: u64) {
-        self.captured_frames_list
-            .push(TimestampedFrame::new(rx_frame, frame_number));
+    fn process_frame(
+        &mut self,
+        rx_frame: CanAnyFrame,
+        interface: Arc<str>,
+        timestamps: CanTimestamps,
+        dropped_frames: u32,
+    ) {
+        let frame_number = self.next_frame_number;
+        self.next_frame_number += 1;
+
+        let timestamped_frame = TimestampedFrame {
+            dropped_frames_before: dropped_frames,
+            ..TimestampedFrame::new(rx_frame, Arc::clone(&interface), frame_number, timestamps)
+        };
+        let timestamp = timestamped_frame.get_timestamp();
+        self.push_to_frame_list(timestamped_frame);
 
-        let old_capture_count = self
+        let key = (Arc::clone(&interface), rx_frame.id());
+
+        let counted_frame = self
             .captured_frames_set
-            .entry(socketcan::Frame::raw_id(&rx_frame))
-            .or_insert(
-                CountedFrame::new(rx_frame), //(rx_frame, 0)
-            )
-            .capture_count;
-
-        self.captured_frames_set.insert(
-            socketcan::Frame::raw_id(&rx_frame),
-            CountedFrame::with_capture_count(rx_frame, old_capture_count + 1),
-        );
+            .entry(key.clone())
+            .or_insert_with(|| CountedFrame::new(rx_frame, Arc::clone(&interface)));
+        counted_frame.update(rx_frame, timestamp);
 
+        if let Some(message_timeout_detector) = &mut self.message_timeout_detector {
+            message_timeout_detector.frame_received(
+                &key,
+                counted_frame,
+                FrameType::of(&rx_frame),
+                timestamp,
+            );
+        }
+
+        *self.interface_frame_counts.entry(interface).or_insert(0) += 1;
         self.total_frame_count += 1;
     }
 
+    fn process_capture_event(&mut self, capture_event: CaptureEvent) {
+        match capture_event {
+            CaptureEvent::Frame(received_frame) => self.process_received_frame(received_frame),
+            CaptureEvent::InterfaceState(interface, state) => {
+                self.interface_states.insert(interface, state);
+            }
+            CaptureEvent::ReceiveError(description) => {
+                self.receive_error_count += 1;
+                self.last_receive_error = Some(description);
+            }
+        }
+    }
+
+    fn process_received_frame(&mut self, received_frame: ReceivedFrame) {
+        let ReceivedFrame {
+            frame,
+            interface,
+            timestamps,
+            dropped_frames,
+        } = received_frame;
+
+        // Frames the kernel dropped never reach us, the socket only reports
+        // how many were lost before the frame which did arrive
+        self.dropped_frame_count += dropped_frames as usize;
+
+        match frame {
+            CanAnyFrame::Error(_) => {
+                self.process_error_frame(frame, interface, timestamps, dropped_frames)
+            }
+            _ => self.process_frame(frame, interface, timestamps, dropped_frames),
+        }
+    }
+
+    /// Error frames are kept in the frame list, so that they show up in the
+    /// timeline next to the traffic they disturbed, but not in the frame set
+    /// or the frame counters as they are not messages on the bus
+    fn process_error_frame(
+        &mut self,
+        rx_frame: CanAnyFrame,
+        interface: Arc<str>,
+        timestamps: CanTimestamps,
+        dropped_frames: u32,
+    ) {
+        let frame_number = self.next_frame_number;
+        self.next_frame_number += 1;
+
+        self.push_to_frame_list(TimestampedFrame {
+            dropped_frames_before: dropped_frames,
+            ..TimestampedFrame::new(rx_frame, interface, frame_number, timestamps)
+        });
+
+        self.error_frame_count += 1;
+    }
+
+    /// Appends the frame to the frame list, evicting the oldest frame if the
+    /// list is full. The list grows as frames arrive, but never reserves room
+    /// past the limit, so that a large limit costs no memory until it is used.
+    fn push_to_frame_list(&mut self, frame: TimestampedFrame) {
+        let frame_count = self.captured_frames_list.len();
+        if frame_count >= self.max_frames {
+            if let Some(evicted_frame) = self.captured_frames_list.pop_front() {
+                self.spill_frame(&evicted_frame);
+                self.evicted_frame_count += 1;
+            }
+        } else if frame_count == self.captured_frames_list.capacity() {
+            self.captured_frames_list
+                .reserve_exact(frame_count.clamp(1, self.max_frames - frame_count));
+        }
+
+        self.captured_frames_list.push_back(frame);
+    }
+
+    /// Writes an evicted frame to the spill file. Spilling stops if the file
+    /// can not be written to, rather than failing the capture. Frames dropped
+    /// by the kernel before the frame are noted in a comment line, so that a
+    /// gap in the log can be told apart from a quiet bus.
+    fn spill_frame(&mut self, frame: &TimestampedFrame) {
+        if let Some(spill_writer) = &mut self.spill_writer {
+            if frame.dropped_frames_before > 0
+                && writeln!(
+                    spill_writer,
+                    "# {} frames dropped on {}",
+                    frame.dropped_frames_before, frame.interface
+                )
+                .is_err()
+            {
+                self.spill_writer = None;
+                return;
+            }
+
+            let record = socketcan::dump::CanDumpRecord {
+                t_us: frame.get_timestamp().as_micros() as u64,
+                device: frame.interface.to_string(),
+                frame: frame.frame,
+            };
+
+            if writeln!(spill_writer, "{}", record).is_err() {
+                self.spill_writer = None;
+            }
+        }
+    }
+
+    fn resume_message_timeouts(&mut self) {
+        if let Some(message_timeout_detector) = &mut self.message_timeout_detector {
+            message_timeout_detector.resume();
+        }
+    }
+
+    fn check_message_timeouts(&mut self) {
+        if let Some(message_timeout_detector) = &mut self.message_timeout_detector {
+            message_timeout_detector.check_timeouts(self.captured_frames_set.iter_mut());
+        }
+    }
+
+    /// Updates the frames per second once a second has passed since the
+    /// last update
+    fn tick_frames_per_second(&mut self) {
+        if self.running_second_timestamp.elapsed().as_secs() >= 1 {
+            // Make spilled frames reach the file at least once a second
+            if let Some(spill_writer) = &mut self.spill_writer {
+                if spill_writer.flush().is_err() {
+                    self.spill_writer = None;
+                }
+            }
+
+            self.update_frames_per_second(self.tot_frames_as_of_last_second);
+            self.tot_frames_as_of_last_second = self.total_frame_count;
+
+            self.running_second_timestamp = Instant::now();
+        }
+    }
+
     /* Updates the number of frames per second, as seen by the Frame Captor
 
        # Arguments
@@ -123,80 +672,236 @@ impl CapturedFrameState {
             self.frames_per_second = self.total_frame_count - tot_frames_as_of_last_second;
             let timestamp = SystemTime::now();
             self.frames_per_second_history
-                .push((self.frames_per_second as f64, timestamp));
+                .push_back((self.frames_per_second as f64, timestamp));
+        }
+
+        while self
+            .frames_per_second_history
+            .front()
+            .and_then(|(_, timestamp)| timestamp.elapsed().ok())
+            .is_some_and(|age| age.as_secs() > FRAMES_PER_SECOND_HISTORY_SECONDS)
+        {
+            self.frames_per_second_history.pop_front();
         }
     }
 }
 
+/// Captures frames from the frame sources on one thread per source. The
+/// capture threads hand received frames over through a channel, so they never
+/// wait for the UI, and the frames are moved into the frame state only when
+/// the UI updates the captor, so every draw works from one consistent state.
+/// A source whose interface is lost is reopened once the interface is back.
+/// Capturing can be stopped and started again, keeping the captured frames.
 pub struct FrameCaptor {
-    captured_frames: Arc<Mutex<CapturedFrameState>>,
-    _capture_thread_handle: std::thread::JoinHandle<()>,
+    captured_frames: CapturedFrameState,
+    capture_event_sender: Sender<CaptureEvent>,
+    capture_event_receiver: Receiver<CaptureEvent>,
+    /// Tells the capture threads to hand their frame sources back
+    stop_capture: Arc<AtomicBool>,
+    capture_threads: Vec<JoinHandle<Box<dyn FrameSource>>>,
+    /// Frame sources of a stopped capture, reopened when it is started again
+    stopped_frame_sources: Vec<Box<dyn FrameSource>>,
 }
 
 impl FrameCaptor {
-    pub fn new(can_interface: String) -> Result<Self> {
-        let rx_sock = CanSocket::open(can_interface.as_str())?;
-        let cap_frame_state = Arc::new(Mutex::new(CapturedFrameState::new()));
-        let thread_cap_frame_state = Arc::clone(&cap_frame_state);
+    /// Captures each of the given frame sources on its own thread, into one
+    /// shared frame state
+    pub fn new(
+        frame_sources: Vec<Box<dyn FrameSource>>,
+        captured_frame_state: CapturedFrameState,
+    ) -> Self {
+        let (capture_event_sender, capture_event_receiver) = mpsc::channel();
 
-        let _capture_thread_handle =
-            std::thread::spawn(move || FrameCaptor::capture(rx_sock, thread_cap_frame_state));
+        let mut frame_captor = Self {
+            captured_frames: captured_frame_state,
+            capture_event_sender,
+            capture_event_receiver,
+            stop_capture: Arc::new(AtomicBool::new(false)),
+            capture_threads: vec![],
+            stopped_frame_sources: vec![],
+        };
+        frame_captor.spawn_capture_threads(frame_sources);
 
-        Ok(Self {
-            captured_frames: cap_frame_state,
-            _capture_thread_handle,
-        })
+        frame_captor
+    }
+
+    fn spawn_capture_threads(&mut self, frame_sources: Vec<Box<dyn FrameSource>>) {
+        self.capture_threads = frame_sources
+            .into_iter()
+            .map(|frame_source| {
+                frame_source
+                    .interfaces()
+                    .into_iter()
+                    .for_each(|interface| self.captured_frames.register_interface(interface));
+
+                let thread_event_sender = self.capture_event_sender.clone();
+                let thread_stop_capture = Arc::clone(&self.stop_capture);
+                std::thread::spawn(move || {
+                    FrameCaptor::capture(frame_source, thread_event_sender, thread_stop_capture)
+                })
+            })
+            .collect();
+
+        self.captured_frames.resume_message_timeouts();
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        !self.capture_threads.is_empty()
+    }
+
+    /// Stops capturing, waiting for the capture threads to finish. The frames
+    /// captured so far are kept.
+    pub fn stop_capture(&mut self) {
+        self.stop_capture.store(true, Ordering::Relaxed);
+        let frame_sources = self
+            .capture_threads
+            .drain(..)
+            .filter_map(|capture_thread| capture_thread.join().ok());
+        self.stopped_frame_sources.extend(frame_sources);
+        self.stop_capture.store(false, Ordering::Relaxed);
+
+        // Nothing is known about the interfaces while they are not captured
+        self.receive_capture_events();
+        self.captured_frames.interface_states.clear();
+    }
+
+    /// Starts a stopped capture again. The frame sources are reopened, so that
+    /// frames which arrived while capturing was stopped are not captured, and
+    /// replays resume where they were stopped.
+    pub fn start_capture(&mut self) -> io::Result<()> {
+        if self.is_capturing() {
+            return Ok(());
+        }
+
+        for frame_source in &mut self.stopped_frame_sources {
+            frame_source.reopen()?;
+        }
+
+        let frame_sources = std::mem::take(&mut self.stopped_frame_sources);
+        self.spawn_capture_threads(frame_sources);
+
+        Ok(())
+    }
+
+    /// Captures the given frame sources instead of the current ones, such as
+    /// after switching interfaces. The frames captured so far are kept.
+    pub fn switch_frame_sources(&mut self, frame_sources: Vec<Box<dyn FrameSource>>) {
+        self.stop_capture();
+        self.stopped_frame_sources.clear();
+        self.spawn_capture_threads(frame_sources);
+    }
+
+    /// Moves the frames received and interface states reported since the
+    /// last update into the frame state. Called by the UI before drawing.
+    pub fn update(&mut self) {
+        self.receive_capture_events();
+
+        // IDs can not be told to have stopped arriving while nothing is
+        // captured, such as while capture is stopped or once a replay ended
+        if self
+            .capture_threads
+            .iter()
+            .any(|capture_thread| !capture_thread.is_finished())
+        {
+            self.captured_frames.check_message_timeouts();
+        }
+        self.captured_frames.tick_frames_per_second();
+    }
+
+    fn receive_capture_events(&mut self) {
+        self.capture_event_receiver
+            .try_iter()
+            .for_each(|capture_event| self.captured_frames.process_capture_event(capture_event));
     }
 
     pub fn clear_captured_frames(&mut self) {
-        let mut b = self.captured_frames.lock().unwrap();
-        b.clear_captured_frames();
+        self.captured_frames.clear_captured_frames();
     }
 
-    pub fn get_captured_frames_list_len(&mut self) -> usize {
-        self.captured_frames
-            .lock()
-            .unwrap()
-            .captured_frames_list
-            .len()
+    pub fn get_captured_frames_list_len(&self) -> usize {
+        self.captured_frames.captured_frames_list.len()
     }
 
-    pub fn get_captured_frames_set_len(&mut self) -> usize {
-        self.captured_frames
-            .lock()
-            .unwrap()
-            .captured_frames_set
-            .len()
+    pub fn get_captured_frames_set_len(&self) -> usize {
+        self.captured_frames.captured_frames_set.len()
+    }
+
+    pub fn get_captured_frames(&self) -> &CapturedFrameState {
+        &self.captured_frames
     }
 
-    pub fn get_captured_frames(&mut self) -> Arc<Mutex<CapturedFrameState>> {
-        Arc::clone(&self.captured_frames)
+    /// Frame number the next captured frame will be given. Frame numbers
+    /// keep counting when the captured frames are cleared.
+    pub fn get_next_frame_number(&self) -> u64 {
+        self.captured_frames.next_frame_number
     }
 
-    pub fn get_unique_frame_count(&mut self) -> usize {
-        let frames = self.captured_frames.lock().unwrap();
-        frames.captured_frames_set.len()
+    pub fn get_unique_frame_count(&self) -> usize {
+        self.captured_frames.captured_frames_set.len()
     }
 
     pub fn get_total_frame_count(&self) -> usize {
-        self.captured_frames.lock().unwrap().total_frame_count
+        self.captured_frames.total_frame_count
+    }
+
+    pub fn get_evicted_frame_count(&self) -> usize {
+        self.captured_frames.evicted_frame_count
+    }
+
+    pub fn get_error_frame_count(&self) -> usize {
+        self.captured_frames.error_frame_count
+    }
+
+    /// Frames the kernel dropped because they were not read from the socket
+    /// in time, which never made it into the frame list
+    pub fn get_dropped_frame_count(&self) -> usize {
+        self.captured_frames.dropped_frame_count
+    }
+
+    /// Times the frame sources failed to receive, with the latest failure
+    pub fn get_receive_errors(&self) -> (usize, Option<&str>) {
+        (
+            self.captured_frames.receive_error_count,
+            self.captured_frames.last_receive_error.as_deref(),
+        )
+    }
+
+    /// Timeouts of IDs which stopped arriving, oldest first, if message
+    /// timeouts are detected
+    pub fn get_timeout_events(&self) -> &[TimeoutEvent] {
+        self.captured_frames
+            .message_timeout_detector
+            .as_ref()
+            .map_or(&[], |message_timeout_detector| {
+                message_timeout_detector.get_timeout_events()
+            })
+    }
+
+    pub fn get_interface_summaries(&self) -> Vec<InterfaceSummary> {
+        self.captured_frames
+            .interface_frame_counts
+            .iter()
+            .map(|(interface, count)| InterfaceSummary {
+                interface: Arc::clone(interface),
+                frame_count: *count,
+                state: self
+                    .captured_frames
+                    .interface_states
+                    .get(interface)
+                    .copied(),
+            })
+            .collect()
     }
 
     pub fn get_frames_per_second(&self) -> usize {
-        self.captured_frames.lock().unwrap().frames_per_second
+        self.captured_frames.frames_per_second
     }
 
     pub fn get_frames_per_second_history(&self) -> Vec<(f64, f64)> {
         let now = SystemTime::now();
 
-        let frame_rate_history = self
-            .captured_frames
-            .lock()
-            .unwrap()
+        self.captured_frames
             .frames_per_second_history
-            .clone();
-
-        frame_rate_history
             .iter()
             .map(|(fps, timestamp)| {
                 (
@@ -207,28 +912,560 @@ impl FrameCaptor {
             .collect()
     }
 
-    fn capture(mut rx_sock: CanSocket, frame_state: Arc<Mutex<CapturedFrameState>>) {
-        let mut running_second_timestamp = Instant::now();
-        let mut tot_frames_as_of_last_second = 0;
-        let mut frame_number: u64 = 0;
+    /// Captures the frame source until capturing is stopped or the source
+    /// runs dry, handing the frame source back so that capturing can be
+    /// started again
+    fn capture(
+        mut frame_source: Box<dyn FrameSource>,
+        event_sender: Sender<CaptureEvent>,
+        stop_capture: Arc<AtomicBool>,
+    ) -> Box<dyn FrameSource> {
+        // Capturing ends early if the receiving end is gone, which happens
+        // once the application quits
+        let _ =
+            FrameCaptor::capture_until_done(frame_source.as_mut(), &event_sender, &stop_capture);
+        frame_source
+    }
+
+    fn capture_until_done(
+        frame_source: &mut dyn FrameSource,
+        event_sender: &Sender<CaptureEvent>,
+        stop_capture: &AtomicBool,
+    ) -> Option<()> {
+        let mut interface_states = vec![];
+        FrameCaptor::report_interface_states(frame_source, &mut interface_states, event_sender)?;
+        let mut last_state_check = Instant::now();
+        let mut consecutive_errors = 0;
+
+        while !stop_capture.load(Ordering::Relaxed) {
+            let received = frame_source.receive();
+            if received.is_ok() {
+                consecutive_errors = 0;
+            }
+
+            match received {
+                Ok(received_frame) => {
+                    // A controller changing its error state reports it with
+                    // an error frame, so the change shows up right away. Other
+                    // error frames, such as a flood of bus errors, are left to
+                    // the regular check.
+                    let is_state_change = matches!(
+                        &received_frame.frame,
+                        CanAnyFrame::Error(error_frame) if is_controller_state_change(error_frame)
+                    );
+                    event_sender
+                        .send(CaptureEvent::Frame(received_frame))
+                        .ok()?;
+
+                    if is_state_change
+                        || last_state_check.elapsed() >= INTERFACE_STATE_POLL_INTERVAL
+                    {
+                        FrameCaptor::report_interface_states(
+                            frame_source,
+                            &mut interface_states,
+                            event_sender,
+                        )?;
+                        last_state_check = Instant::now();
+                    }
+                }
+                // The source has run dry, such as a replayed log reaching its end
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Some(()),
+                Err(e) if is_interface_lost(&e) => {
+                    FrameCaptor::report_receive_error(frame_source, &e, event_sender)?;
+
+                    while !stop_capture.load(Ordering::Relaxed) {
+                        FrameCaptor::report_interface_states(
+                            frame_source,
+                            &mut interface_states,
+                            event_sender,
+                        )?;
 
-        loop {
-            if let Ok(rx_frame) = rx_sock.receive() {
-                frame_state
-                    .lock()
-                    .unwrap()
-                    .process_frame(rx_frame, frame_number);
-                frame_number += 1;
+                        if interface_states
+                            .iter()
+                            .all(|(_, state)| *state != InterfaceState::Down)
+                            && frame_source.reopen().is_ok()
+                        {
+                            break;
+                        }
+
+                        FrameCaptor::sleep_unless_stopped(
+                            INTERFACE_STATE_POLL_INTERVAL,
+                            stop_capture,
+                        );
+                    }
+                }
+                // Checking the interface states now and then, even when no
+                // frames arrive, keeps a silent bus from hiding a state change
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                    if last_state_check.elapsed() >= INTERFACE_STATE_POLL_INTERVAL {
+                        FrameCaptor::report_interface_states(
+                            frame_source,
+                            &mut interface_states,
+                            event_sender,
+                        )?;
+                        last_state_check = Instant::now();
+                    }
+                }
+                // A source which keeps failing, such as one which is fed
+                // garbage, is backed off from rather than spun on
+                Err(e) => {
+                    FrameCaptor::report_receive_error(frame_source, &e, event_sender)?;
+                    consecutive_errors += 1;
+                    FrameCaptor::sleep_unless_stopped(
+                        FrameCaptor::get_receive_error_backoff(consecutive_errors),
+                        stop_capture,
+                    );
+                }
             }
+        }
 
-            if running_second_timestamp.elapsed().as_secs() >= 1 {
-                let mut f = frame_state.lock().unwrap();
+        Some(())
+    }
+
+    /// Time to wait after the given number of receive errors in a row. A
+    /// single error, such as a garbled line from a serial adapter, is not
+    /// waited on.
+    fn get_receive_error_backoff(consecutive_errors: u32) -> Duration {
+        match consecutive_errors {
+            0 | 1 => Duration::ZERO,
+            _ => RECEIVE_ERROR_BACKOFF
+                .saturating_mul(1 << (consecutive_errors - 2).min(16))
+                .min(INTERFACE_STATE_POLL_INTERVAL),
+        }
+    }
+
+    fn report_receive_error(
+        frame_source: &dyn FrameSource,
+        error: &io::Error,
+        event_sender: &Sender<CaptureEvent>,
+    ) -> Option<()> {
+        let description = format!("{}: {}", frame_source.name(), error);
+        event_sender
+            .send(CaptureEvent::ReceiveError(description))
+            .ok()
+    }
 
-                f.update_frames_per_second(tot_frames_as_of_last_second);
-                tot_frames_as_of_last_second = f.total_frame_count;
+    fn sleep_unless_stopped(duration: Duration, stop_capture: &AtomicBool) {
+        let wake_up = Instant::now() + duration;
 
-                running_second_timestamp = Instant::now();
+        while !stop_capture.load(Ordering::Relaxed) {
+            let remaining = wake_up.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return;
             }
+            std::thread::sleep(remaining.min(RECEIVE_TIMEOUT));
         }
     }
+
+    /// Sends the states of the source's interfaces which changed since they
+    /// were last reported
+    fn report_interface_states(
+        frame_source: &dyn FrameSource,
+        reported_states: &mut Vec<(Arc<str>, InterfaceState)>,
+        event_sender: &Sender<CaptureEvent>,
+    ) -> Option<()> {
+        let interface_states = frame_source.interface_states();
+
+        for (interface, state) in &interface_states {
+            if !reported_states.contains(&(Arc::clone(interface), *state)) {
+                event_sender
+                    .send(CaptureEvent::InterfaceState(Arc::clone(interface), *state))
+                    .ok()?;
+            }
+        }
+
+        *reported_states = interface_states;
+        Some(())
+    }
+}
+
+impl Drop for FrameCaptor {
+    /// Stops the capture threads, so that the frame sources are closed
+    /// cleanly before the application exits
+    fn drop(&mut self) {
+        self.stop_capture();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame_source::MemorySource;
+    use embedded_can::StandardId;
+    use socketcan::CanDataFrame;
+
+    fn data_frame(id: u16, data: &[u8]) -> CanAnyFrame {
+        CanAnyFrame::Normal(CanDataFrame::new(StandardId::new(id).unwrap(), data).unwrap())
+    }
+
+    fn standard_id(id: u16) -> embedded_can::Id {
+        embedded_can::Id::Standard(StandardId::new(id).unwrap())
+    }
+
+    /// Updates the captor until it has captured the given number of frames,
+    /// failing the test if they do not arrive in time
+    fn wait_for_frames(frame_captor: &mut FrameCaptor, frame_count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while frame_captor.get_total_frame_count() < frame_count {
+            assert!(
+                Instant::now() < deadline,
+                "frames were not captured in time"
+            );
+            std::thread::sleep(Duration::from_millis(5));
+            frame_captor.update();
+        }
+    }
+
+    #[test]
+    fn data_len_is_the_payload_length() {
+        let fd_frame = CanAnyFrame::Fd(
+            socketcan::CanFdFrame::new(StandardId::new(0x10).unwrap(), &[0; 12]).unwrap(),
+        );
+        assert_eq!(fd_frame.dlc(), 9);
+        assert_eq!(get_data_len(&fd_frame), 12);
+
+        assert_eq!(get_data_len(&data_frame(0x10, &[1, 2, 3])), 3);
+
+        let remote_frame = CanAnyFrame::Remote(
+            socketcan::CanRemoteFrame::new_remote(StandardId::new(0x10).unwrap(), 4).unwrap(),
+        );
+        assert_eq!(get_data_len(&remote_frame), 0);
+    }
+
+    #[test]
+    fn capture_stop_and_restart() {
+        let source = MemorySource::new("mem0");
+        source.push(data_frame(0x100, &[1]));
+        source.push(data_frame(0x200, &[2, 3]));
+        source.push(data_frame(0x100, &[4]));
+
+        let mut frame_captor =
+            FrameCaptor::new(vec![Box::new(source.clone())], CapturedFrameState::new());
+        assert!(frame_captor.is_capturing());
+        wait_for_frames(&mut frame_captor, 3);
+
+        frame_captor.stop_capture();
+        assert!(!frame_captor.is_capturing());
+
+        // Frames which arrive while stopped are not captured
+        source.push(data_frame(0x300, &[5]));
+        frame_captor.update();
+        assert_eq!(frame_captor.get_total_frame_count(), 3);
+
+        frame_captor.start_capture().unwrap();
+        assert!(frame_captor.is_capturing());
+        assert_eq!(source.get_reopen_count(), 1);
+
+        source.push(data_frame(0x200, &[6, 7]));
+        wait_for_frames(&mut frame_captor, 4);
+        frame_captor.stop_capture();
+
+        let captured_frames = frame_captor.get_captured_frames();
+        let frame_numbers = captured_frames
+            .captured_frames_list
+            .iter()
+            .map(|frame| frame.frame_number)
+            .collect::<Vec<_>>();
+        assert_eq!(frame_numbers, [0, 1, 2, 3]);
+        assert_eq!(frame_captor.get_unique_frame_count(), 2);
+
+        let interface: Arc<str> = "mem0".into();
+        let counted_frame =
+            &captured_frames.captured_frames_set[&(Arc::clone(&interface), standard_id(0x200))];
+        assert_eq!(counted_frame.capture_count, 2);
+        assert_eq!(counted_frame.frame.data(), [6, 7]);
+        assert!(!captured_frames
+            .captured_frames_set
+            .contains_key(&(interface, standard_id(0x300))));
+    }
+
+    #[test]
+    fn standard_and_extended_ids_are_counted_apart() {
+        let extended_id = embedded_can::ExtendedId::new(0x123).unwrap();
+        let source = MemorySource::new("mem0");
+        source.push(data_frame(0x123, &[1]));
+        source.push(CanAnyFrame::Normal(
+            CanDataFrame::new(extended_id, &[2, 3]).unwrap(),
+        ));
+        source.push(data_frame(0x123, &[4]));
+
+        let mut frame_captor =
+            FrameCaptor::new(vec![Box::new(source.clone())], CapturedFrameState::new());
+        wait_for_frames(&mut frame_captor, 3);
+        assert_eq!(frame_captor.get_unique_frame_count(), 2);
+
+        let interface: Arc<str> = "mem0".into();
+        let captured_frames_set = &frame_captor.get_captured_frames().captured_frames_set;
+        let standard_frame = &captured_frames_set[&(Arc::clone(&interface), standard_id(0x123))];
+        assert_eq!(standard_frame.capture_count, 2);
+        assert_eq!(standard_frame.frame.data(), [4]);
+
+        let extended_frame =
+            &captured_frames_set[&(interface, embedded_can::Id::Extended(extended_id))];
+        assert_eq!(extended_frame.capture_count, 1);
+        assert_eq!(extended_frame.frame.data(), [2, 3]);
+    }
+
+    #[test]
+    fn receive_errors_are_reported() {
+        let source = MemorySource::new("mem0");
+        source.push_error(io::ErrorKind::InvalidData.into());
+        source.push_error(io::ErrorKind::InvalidData.into());
+        source.push(data_frame(0x100, &[1]));
+
+        let mut frame_captor =
+            FrameCaptor::new(vec![Box::new(source.clone())], CapturedFrameState::new());
+        wait_for_frames(&mut frame_captor, 1);
+
+        let (receive_error_count, last_receive_error) = frame_captor.get_receive_errors();
+        assert_eq!(receive_error_count, 2);
+        assert_eq!(last_receive_error, Some("mem0: invalid data"));
+    }
+
+    #[test]
+    fn receive_errors_back_off() {
+        assert_eq!(FrameCaptor::get_receive_error_backoff(1), Duration::ZERO);
+        assert_eq!(
+            FrameCaptor::get_receive_error_backoff(2),
+            RECEIVE_ERROR_BACKOFF
+        );
+        assert_eq!(
+            FrameCaptor::get_receive_error_backoff(3),
+            RECEIVE_ERROR_BACKOFF * 2
+        );
+        assert_eq!(
+            FrameCaptor::get_receive_error_backoff(100),
+            INTERFACE_STATE_POLL_INTERVAL
+        );
+    }
+
+    #[test]
+    fn stopped_capture_does_not_time_out() {
+        let message_timeouts = MessageTimeouts {
+            period_multiple: 1.0,
+            configured_periods: HashMap::from([(0x100, Duration::from_millis(50))]),
+        };
+        let source = MemorySource::new("mem0");
+        source.push(data_frame(0x100, &[1]));
+
+        let mut frame_captor = FrameCaptor::new(
+            vec![Box::new(source.clone())],
+            CapturedFrameState::new().with_message_timeouts(message_timeouts),
+        );
+        wait_for_frames(&mut frame_captor, 1);
+
+        frame_captor.stop_capture();
+        std::thread::sleep(Duration::from_millis(200));
+        frame_captor.update();
+        assert!(frame_captor.get_timeout_events().is_empty());
+
+        // The time capture was stopped for does not count once it is started
+        // again, but the ID still times out if it does not arrive after that
+        frame_captor.start_capture().unwrap();
+        frame_captor.update();
+        assert!(frame_captor.get_timeout_events().is_empty());
+
+        std::thread::sleep(Duration::from_millis(100));
+        frame_captor.update();
+        assert_eq!(frame_captor.get_timeout_events().len(), 1);
+    }
+
+    #[test]
+    fn clearing_keeps_frame_numbers_counting() {
+        let source = MemorySource::new("mem0");
+        source.push(data_frame(0x100, &[1]));
+        source.push(data_frame(0x100, &[2]));
+
+        let mut frame_captor =
+            FrameCaptor::new(vec![Box::new(source.clone())], CapturedFrameState::new());
+        wait_for_frames(&mut frame_captor, 2);
+
+        frame_captor.clear_captured_frames();
+        assert_eq!(frame_captor.get_captured_frames_list_len(), 0);
+        assert_eq!(frame_captor.get_captured_frames_set_len(), 0);
+
+        source.push(data_frame(0x100, &[3]));
+        wait_for_frames(&mut frame_captor, 1);
+        assert_eq!(
+            frame_captor.get_captured_frames().captured_frames_list[0].frame_number,
+            2
+        );
+    }
+
+    #[test]
+    fn history_limit_evicts_and_spills_the_oldest_frames() {
+        let spill_path =
+            std::env::temp_dir().join(format!("can-viewer-tui-spill-{}.log", std::process::id()));
+        let source = MemorySource::new("mem0");
+        for data in 0..5 {
+            source.push(data_frame(0x100 + u16::from(data), &[data]));
+        }
+
+        let mut frame_captor = FrameCaptor::new(
+            vec![Box::new(source)],
+            CapturedFrameState::with_history_limit(FrameHistoryLimit {
+                max_frames: 2,
+                spill_file: Some(File::create(&spill_path).unwrap()),
+            }),
+        );
+        wait_for_frames(&mut frame_captor, 5);
+        frame_captor.stop_capture();
+
+        assert_eq!(frame_captor.get_captured_frames_list_len(), 2);
+        assert_eq!(frame_captor.get_evicted_frame_count(), 3);
+        assert!(
+            frame_captor
+                .get_captured_frames()
+                .captured_frames_list
+                .capacity()
+                <= 2
+        );
+        let frame_numbers = frame_captor
+            .get_captured_frames()
+            .captured_frames_list
+            .iter()
+            .map(|frame| frame.frame_number)
+            .collect::<Vec<_>>();
+        assert_eq!(frame_numbers, vec![3, 4]);
+        // Evicted frames still count towards the frame set
+        assert_eq!(frame_captor.get_captured_frames_set_len(), 5);
+
+        // Dropping the captor flushes the spill file
+        drop(frame_captor);
+        let spill_log = std::fs::read_to_string(&spill_path).unwrap();
+        let _ = std::fs::remove_file(&spill_path);
+
+        let mut spill_reader = socketcan::dump::Reader::from_reader(spill_log.as_bytes());
+        let mut spilled_frames = Vec::new();
+        while let Some(record) = spill_reader.next_record().unwrap() {
+            assert_eq!(record.device, "mem0");
+            spilled_frames.push((get_raw_id(record.frame.id()), record.frame.data().to_vec()));
+        }
+        assert_eq!(
+            spilled_frames,
+            vec![(0x100, vec![0]), (0x101, vec![1]), (0x102, vec![2])]
+        );
+    }
+
+    #[test]
+    fn spilled_frames_can_be_replayed() {
+        let spill_path = std::env::temp_dir().join(format!(
+            "can-viewer-tui-replay-spill-{}.log",
+            std::process::id()
+        ));
+        let mut captured_frames = CapturedFrameState::with_history_limit(FrameHistoryLimit {
+            max_frames: 1,
+            spill_file: Some(File::create(&spill_path).unwrap()),
+        });
+        for frame_number in 0..3 {
+            let mut frame = TimestampedFrame::new(
+                data_frame(0x100, &[frame_number as u8]),
+                "mem0".into(),
+                frame_number,
+                CanTimestamps {
+                    socket: Some(UNIX_EPOCH + Duration::from_secs(1 + frame_number)),
+                    ..Default::default()
+                },
+            );
+            frame.dropped_frames_before = frame_number as u32;
+            captured_frames.push_to_frame_list(frame);
+        }
+        // Dropping the state flushes the spill file
+        drop(captured_frames);
+
+        let mut replay_source =
+            crate::replay::ReplaySource::open(spill_path.to_str().unwrap(), 0.0).unwrap();
+        let first_frame = replay_source.receive();
+        let second_frame = replay_source.receive();
+        let end_of_log = replay_source.receive();
+        let spill_log = std::fs::read_to_string(&spill_path).unwrap();
+        let _ = std::fs::remove_file(&spill_path);
+
+        // The dropped frames before the second frame are noted in a comment
+        assert!(
+            spill_log.contains("# 1 frames dropped on mem0"),
+            "{}",
+            spill_log
+        );
+        let first_frame = first_frame.unwrap();
+        assert_eq!(&*first_frame.interface, "mem0");
+        assert_eq!(first_frame.frame.data(), [0]);
+        assert_eq!(
+            first_frame.timestamps.socket,
+            Some(UNIX_EPOCH + Duration::from_secs(1))
+        );
+        assert_eq!(second_frame.unwrap().frame.data(), [1]);
+        assert_eq!(
+            end_of_log.err().unwrap().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("statistic was not measured");
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn period_statistics_are_unset_until_measured() {
+        let period_statistics = PeriodStatistics::default();
+        assert_eq!(period_statistics.get_count(), 0);
+        assert_eq!(period_statistics.get_min(), None);
+        assert_eq!(period_statistics.get_max(), None);
+        assert_eq!(period_statistics.get_mean(), None);
+        assert_eq!(period_statistics.get_jitter(), None);
+        assert_eq!(period_statistics.get_rate(), None);
+    }
+
+    #[test]
+    fn period_statistics_of_known_periods() {
+        let mut period_statistics = PeriodStatistics::default();
+        for period in [0.1, 0.4, 0.2, 0.3] {
+            period_statistics.add(period);
+        }
+
+        assert_eq!(period_statistics.get_count(), 4);
+        assert_close(period_statistics.get_min(), 0.1);
+        assert_close(period_statistics.get_max(), 0.4);
+        assert_close(period_statistics.get_mean(), 0.25);
+        // Population standard deviation of the periods
+        assert_close(period_statistics.get_jitter(), 0.0125_f64.sqrt());
+
+        let mut steady_statistics = PeriodStatistics::default();
+        for _ in 0..10 {
+            steady_statistics.add(0.02);
+        }
+        assert_close(steady_statistics.get_jitter(), 0.0);
+        assert_close(steady_statistics.get_rate(), 50.0);
+    }
+
+    #[test]
+    fn period_rate_follows_changes() {
+        let mut period_statistics = PeriodStatistics::default();
+        period_statistics.add(0.1);
+        assert_close(period_statistics.get_current_period(), 0.1);
+
+        // Each period moves the moving average a tenth of the way towards it
+        period_statistics.add(0.2);
+        assert_close(period_statistics.get_current_period(), 0.11);
+
+        for _ in 0..49 {
+            period_statistics.add(0.2);
+        }
+        let current_period = 0.2 - 0.1 * 0.9_f64.powi(50);
+        assert_close(period_statistics.get_current_period(), current_period);
+        assert_close(period_statistics.get_rate(), 1.0 / current_period);
+        // The mean still remembers the faster start
+        assert!(period_statistics.get_mean().unwrap() < current_period);
+
+        // Frames which arrive at once have no rate rather than an infinite one
+        let mut burst_statistics = PeriodStatistics::default();
+        burst_statistics.add(0.0);
+        assert_eq!(burst_statistics.get_rate(), None);
+    }
 }
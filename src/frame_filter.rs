@@ -1,15 +1,590 @@
-use crate::frame::TimestampedFrame;
+use crate::frame::get_data_len;
 use embedded_can::Frame;
+use regex::{Regex, RegexBuilder};
+use socketcan::{CanAnyFrame, CanFilter};
 
-type FrameFilterCallback = fn(frame: &TimestampedFrame, filter_ids: &[embedded_can::Id]) -> bool;
+// Flag and masks of the CAN ID word used by SocketCAN filters, see linux/can.h
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_SFF_MASK: u32 = 0x0000_07ff;
+const CAN_EFF_MASK: u32 = 0x1fff_ffff;
 
-pub struct FrameIdFilter {
-    pub ids: Vec<embedded_can::Id>,
-    pub filter_callback: FrameFilterCallback,
+/// Value of a frame which a filter expression compares against a number
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Id,
+    Dlc,
+    Len,
+    Byte(usize),
 }
 
-pub fn filter_frame_on_ids(frame: &TimestampedFrame, filter_ids: &[embedded_can::Id]) -> bool {
-    filter_ids
-        .iter()
-        .any(|filter_id| *filter_id == frame.frame.id())
+impl Operand {
+    /// Value of the operand for the frame, or `None` for a payload byte past
+    /// the end of the payload
+    fn get_value(&self, frame: &CanAnyFrame) -> Option<u32> {
+        match self {
+            Operand::Id => Some(match frame.id() {
+                socketcan::Id::Standard(standard_id) => standard_id.as_raw() as u32,
+                socketcan::Id::Extended(extended_id) => extended_id.as_raw(),
+            }),
+            Operand::Dlc => Some(frame.dlc() as u32),
+            Operand::Len => Some(get_data_len(frame) as u32),
+            Operand::Byte(i) => frame.data().get(*i).map(|byte| u32::from(*byte)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn compare(&self, left: u32, right: u32) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+/// Parsed filter expression, which decides whether a frame is shown
+#[derive(Clone, Debug)]
+enum FilterExpression {
+    /// Compares a value of the frame, masked if a mask is given
+    Compare {
+        operand: Operand,
+        mask: Option<u32>,
+        comparison: Comparison,
+        value: u32,
+    },
+    /// Matches IDs within the range, both ends included
+    IdRange(u32, u32),
+    Standard,
+    Extended,
+    Fd,
+    Remote,
+    Error,
+    /// Matches the payload as a hex string without separators, ignoring case
+    PayloadRegex(Regex),
+    Not(Box<FilterExpression>),
+    And(Box<FilterExpression>, Box<FilterExpression>),
+    Or(Box<FilterExpression>, Box<FilterExpression>),
+}
+
+impl FilterExpression {
+    fn matches(&self, frame: &CanAnyFrame) -> bool {
+        match self {
+            FilterExpression::Compare {
+                operand,
+                mask,
+                comparison,
+                value,
+            } => operand.get_value(frame).is_some_and(|frame_value| {
+                comparison.compare(frame_value & mask.unwrap_or(u32::MAX), *value)
+            }),
+            FilterExpression::IdRange(start, end) => Operand::Id
+                .get_value(frame)
+                .is_some_and(|id| (*start..=*end).contains(&id)),
+            FilterExpression::Standard => !frame.is_extended(),
+            FilterExpression::Extended => frame.is_extended(),
+            FilterExpression::Fd => matches!(frame, CanAnyFrame::Fd(_)),
+            FilterExpression::Remote => matches!(frame, CanAnyFrame::Remote(_)),
+            FilterExpression::Error => matches!(frame, CanAnyFrame::Error(_)),
+            FilterExpression::PayloadRegex(regex) => {
+                let payload = frame
+                    .data()
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<String>();
+                regex.is_match(&payload)
+            }
+            FilterExpression::Not(expression) => !expression.matches(frame),
+            FilterExpression::And(left, right) => left.matches(frame) && right.matches(frame),
+            FilterExpression::Or(left, right) => left.matches(frame) || right.matches(frame),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(u32),
+    Text(String),
+    Symbol(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Number(number) => write!(f, "'{}'", number),
+            Token::Text(text) => write!(f, "\"{}\"", text),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+        }
+    }
+}
+
+/// Symbols of the expression language, the longer ones first so that they
+/// are not taken for the shorter symbols they begin with
+const SYMBOLS: [&str; 16] = [
+    "==", "!=", "<=", ">=", "&&", "||", "..", "<", ">", "&", "!", "~", "(", ")", "[", "]",
+];
+
+fn parse_number(text: &str) -> Result<u32, String> {
+    let number = match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+
+    number.map_err(|_| format!("Invalid number '{}'", text))
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = text.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else if c == '"' {
+            let end = rest[1..]
+                .find('"')
+                .ok_or_else(|| String::from("Unterminated string"))?;
+            tokens.push(Token::Text(rest[1..=end].to_string()));
+            rest = &rest[end + 2..];
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            tokens.push(match c.is_ascii_digit() {
+                true => Token::Number(parse_number(word)?),
+                false => Token::Word(word.to_lowercase()),
+            });
+            rest = &rest[end..];
+        } else {
+            return Err(format!("Unexpected character '{}'", c));
+        }
+
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser of filter expressions, where `or` binds looser
+/// than `and`, which binds looser than `not`
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| String::from("Unexpected end of expression"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    /// Consumes the next token if it is one of the given words or symbols
+    fn accept(&mut self, alternatives: &[&str]) -> bool {
+        let is_accepted = match self.peek() {
+            Some(Token::Word(word)) => alternatives.contains(&word.as_str()),
+            Some(Token::Symbol(symbol)) => alternatives.contains(symbol),
+            _ => false,
+        };
+        if is_accepted {
+            self.position += 1;
+        }
+
+        is_accepted
+    }
+
+    fn expect(&mut self, alternatives: &[&str]) -> Result<(), String> {
+        match self.accept(alternatives) {
+            true => Ok(()),
+            false => Err(format!(
+                "Expected '{}' but found {}",
+                alternatives[0],
+                self.peek()
+                    .map_or_else(|| String::from("the end"), |token| token.to_string())
+            )),
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, String> {
+        match self.next()? {
+            Token::Number(number) => Ok(number),
+            token => Err(format!("Expected a number but found {}", token)),
+        }
+    }
+
+    fn or_expression(&mut self) -> Result<FilterExpression, String> {
+        let mut expression = self.and_expression()?;
+        while self.accept(&["or", "||"]) {
+            expression =
+                FilterExpression::Or(Box::new(expression), Box::new(self.and_expression()?));
+        }
+
+        Ok(expression)
+    }
+
+    fn and_expression(&mut self) -> Result<FilterExpression, String> {
+        let mut expression = self.unary_expression()?;
+        while self.accept(&["and", "&&"]) {
+            expression =
+                FilterExpression::And(Box::new(expression), Box::new(self.unary_expression()?));
+        }
+
+        Ok(expression)
+    }
+
+    fn unary_expression(&mut self) -> Result<FilterExpression, String> {
+        match self.accept(&["not", "!"]) {
+            true => Ok(FilterExpression::Not(Box::new(self.unary_expression()?))),
+            false => self.primary_expression(),
+        }
+    }
+
+    fn primary_expression(&mut self) -> Result<FilterExpression, String> {
+        let word = match self.next()? {
+            Token::Symbol("(") => {
+                let expression = self.or_expression()?;
+                self.expect(&[")"])?;
+                return Ok(expression);
+            }
+            Token::Word(word) => word,
+            token => return Err(format!("Unexpected {}", token)),
+        };
+
+        match word.as_str() {
+            "std" | "standard" => Ok(FilterExpression::Standard),
+            "ext" | "extended" => Ok(FilterExpression::Extended),
+            "fd" => Ok(FilterExpression::Fd),
+            "rtr" | "remote" => Ok(FilterExpression::Remote),
+            "error" => Ok(FilterExpression::Error),
+            "id" => match self.peek() {
+                Some(Token::Word(word)) if word == "in" || word == "not" => {
+                    let is_excluded = self.accept(&["not"]);
+                    self.expect(&["in"])?;
+                    let range = self.id_range()?;
+                    Ok(match is_excluded {
+                        true => FilterExpression::Not(Box::new(range)),
+                        false => range,
+                    })
+                }
+                _ => self.comparison(Operand::Id),
+            },
+            "dlc" => self.comparison(Operand::Dlc),
+            "len" => self.comparison(Operand::Len),
+            "data" if self.accept(&["["]) => {
+                let index = self.number()? as usize;
+                self.expect(&["]"])?;
+                self.comparison(Operand::Byte(index))
+            }
+            "data" => {
+                self.expect(&["~", "matches"])?;
+                match self.next()? {
+                    Token::Text(pattern) => RegexBuilder::new(&pattern)
+                        .case_insensitive(true)
+                        .build()
+                        .map(FilterExpression::PayloadRegex)
+                        .map_err(|_| format!("Invalid payload regex \"{}\"", pattern)),
+                    token => Err(format!("Expected a quoted regex but found {}", token)),
+                }
+            }
+            _ => Err(format!("Unknown filter '{}'", word)),
+        }
+    }
+
+    fn id_range(&mut self) -> Result<FilterExpression, String> {
+        let start = self.number()?;
+        self.expect(&[".."])?;
+        let end = self.number()?;
+
+        Ok(FilterExpression::IdRange(start, end))
+    }
+
+    /// Comparison of the operand, masked with `& MASK` if given. An operand
+    /// without a comparison matches when it is not zero, such as
+    /// `data[2] & 0x80`.
+    fn comparison(&mut self, operand: Operand) -> Result<FilterExpression, String> {
+        let mask = match self.accept(&["&"]) {
+            true => Some(self.number()?),
+            false => None,
+        };
+
+        let comparison = match self.peek() {
+            Some(Token::Symbol("==")) => Comparison::Equal,
+            Some(Token::Symbol("!=")) => Comparison::NotEqual,
+            Some(Token::Symbol("<")) => Comparison::Less,
+            Some(Token::Symbol("<=")) => Comparison::LessOrEqual,
+            Some(Token::Symbol(">")) => Comparison::Greater,
+            Some(Token::Symbol(">=")) => Comparison::GreaterOrEqual,
+            _ => {
+                return Ok(FilterExpression::Compare {
+                    operand,
+                    mask,
+                    comparison: Comparison::NotEqual,
+                    value: 0,
+                })
+            }
+        };
+        self.position += 1;
+
+        Ok(FilterExpression::Compare {
+            operand,
+            mask,
+            comparison,
+            value: self.number()?,
+        })
+    }
+}
+
+/// Filter on the frames shown, given as an expression such as
+/// `id in 0x100..0x1ff and not (ext or data[2] & 0x80)`
+#[derive(Clone, Debug)]
+pub struct FrameFilter {
+    /// The expression as it was typed in
+    pub text: String,
+    expression: FilterExpression,
+}
+
+impl FrameFilter {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+
+        let expression = parser.or_expression()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected {}", token));
+        }
+
+        Ok(Self {
+            text: text.trim().to_string(),
+            expression,
+        })
+    }
+
+    /// Filter which lets through the frames with any of the given ID's, a
+    /// standard ID not matching an extended ID with the same numeric value
+    pub fn from_ids(filter_ids: &[embedded_can::Id]) -> Self {
+        let text = filter_ids
+            .iter()
+            .map(|filter_id| match filter_id {
+                embedded_can::Id::Standard(standard_id) => {
+                    format!("(std and id == 0x{:x})", standard_id.as_raw())
+                }
+                embedded_can::Id::Extended(extended_id) => {
+                    format!("(ext and id == 0x{:x})", extended_id.as_raw())
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" or ");
+
+        Self::parse(&text).expect("Failed to build filter from CAN ID's!")
+    }
+
+    /// Filter which lets through the frames matching both filters
+    pub fn and(self, other: FrameFilter) -> Self {
+        Self {
+            text: format!("({}) and ({})", self.text, other.text),
+            expression: FilterExpression::And(
+                Box::new(self.expression),
+                Box::new(other.expression),
+            ),
+        }
+    }
+
+    pub fn matches(&self, frame: &CanAnyFrame) -> bool {
+        self.expression.matches(frame)
+    }
+}
+
+/// Builds SocketCAN raw filters which let through the frames with the given
+/// ID's, as well as frames matching any of the given ID and mask pairs.
+/// The filters match both data and remote frames of an ID, and a standard ID
+/// does not match an extended ID with the same numeric value.
+pub fn get_socket_filters(
+    filter_ids: &[embedded_can::Id],
+    id_masks: &[(u32, u32)],
+) -> Vec<CanFilter> {
+    let id_filters = filter_ids.iter().map(|filter_id| match filter_id {
+        embedded_can::Id::Standard(standard_id) => {
+            CanFilter::new(standard_id.as_raw() as u32, CAN_EFF_FLAG | CAN_SFF_MASK)
+        }
+        embedded_can::Id::Extended(extended_id) => CanFilter::new(
+            extended_id.as_raw() | CAN_EFF_FLAG,
+            CAN_EFF_FLAG | CAN_EFF_MASK,
+        ),
+    });
+
+    let mask_filters = id_masks.iter().map(|(id, mask)| CanFilter::new(*id, *mask));
+
+    id_filters.chain(mask_filters).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_can::{ExtendedId, Id, StandardId};
+    use socketcan::{CanDataFrame, CanFdFrame, CanRemoteFrame};
+
+    fn standard_frame(id: u16, data: &[u8]) -> CanAnyFrame {
+        CanAnyFrame::Normal(CanDataFrame::new(StandardId::new(id).unwrap(), data).unwrap())
+    }
+
+    fn extended_frame(id: u32, data: &[u8]) -> CanAnyFrame {
+        CanAnyFrame::Normal(CanDataFrame::new(ExtendedId::new(id).unwrap(), data).unwrap())
+    }
+
+    fn remote_frame(id: u16, dlc: usize) -> CanAnyFrame {
+        CanAnyFrame::Remote(CanRemoteFrame::new_remote(StandardId::new(id).unwrap(), dlc).unwrap())
+    }
+
+    fn fd_frame(id: u16, data: &[u8]) -> CanAnyFrame {
+        CanAnyFrame::Fd(CanFdFrame::new(StandardId::new(id).unwrap(), data).unwrap())
+    }
+
+    fn matches(text: &str, frame: &CanAnyFrame) -> bool {
+        FrameFilter::parse(text).unwrap().matches(frame)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let frame = standard_frame(0x123, &[1, 2, 3, 4]);
+        assert!(matches("rtr || dlc >= 4 && len == 4", &frame));
+        assert!(matches("id == 0x123 or ext and fd", &frame));
+        assert!(!matches("(id == 0x123 or ext) and fd", &frame));
+        assert!(matches("rtr or dlc == 8 or id == 0x123", &frame));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        let frame = standard_frame(0x123, &[]);
+        assert!(matches("not ext and std", &frame));
+        assert!(!matches("not (std and id == 0x123)", &frame));
+        assert!(matches("!!std", &frame));
+        assert!(!matches("not std or ext", &frame));
+    }
+
+    #[test]
+    fn masks_values_before_comparing() {
+        let frame = standard_frame(0x123, &[0x81, 0x00]);
+        assert!(matches("id & 0x700 == 0x100", &frame));
+        assert!(!matches("id & 0x0ff == 0x100", &frame));
+        assert!(matches("data[0] & 0x80", &frame));
+        assert!(!matches("data[1] & 0x80", &frame));
+        assert!(matches("data[0] & 0x0f < 2", &frame));
+    }
+
+    #[test]
+    fn payload_bytes_past_the_end_never_match() {
+        let frame = standard_frame(0x123, &[0]);
+        assert!(matches("data[0] == 0", &frame));
+        assert!(!matches("data[1] == 0", &frame));
+        assert!(!matches("data[9] != 0", &frame));
+        assert!(matches("not data[9] == 0", &frame));
+    }
+
+    #[test]
+    fn matches_id_ranges() {
+        assert!(matches("id in 0x100..0x1ff", &standard_frame(0x100, &[])));
+        assert!(matches("id in 0x100..0x1ff", &standard_frame(0x1ff, &[])));
+        assert!(!matches("id in 0x100..0x1ff", &standard_frame(0x200, &[])));
+        assert!(matches("id not in 0x100..0x1ff", &remote_frame(0x200, 1)));
+        assert!(!matches(
+            "id not in 0x100..0x1ff",
+            &standard_frame(0x150, &[])
+        ));
+    }
+
+    #[test]
+    fn matches_frame_kinds() {
+        let extended = extended_frame(0x123, &[]);
+        assert!(matches("ext and id == 0x123", &extended));
+        assert!(!matches("std", &extended));
+        assert!(matches(
+            "rtr and dlc == 3 and len == 0",
+            &remote_frame(0x7ff, 3)
+        ));
+        assert!(matches("fd and len == 12", &fd_frame(0x10, &[0; 12])));
+        assert!(!matches("fd", &standard_frame(0x10, &[])));
+    }
+
+    #[test]
+    fn matches_payload_regexes_ignoring_case() {
+        let frame = standard_frame(0x123, &[0x00, 0xff, 0xab]);
+        assert!(matches("data ~ \"^00FF\"", &frame));
+        assert!(matches("data matches \"ab$\"", &frame));
+        assert!(!matches("data ~ \"^ff\"", &frame));
+    }
+
+    #[test]
+    fn id_filters_tell_standard_and_extended_ids_apart() {
+        let frame_filter = FrameFilter::from_ids(&[
+            Id::Standard(StandardId::new(0x123).unwrap()),
+            Id::Extended(ExtendedId::new(0x456).unwrap()),
+        ]);
+        assert_eq!(
+            frame_filter.text,
+            "(std and id == 0x123) or (ext and id == 0x456)"
+        );
+
+        assert!(frame_filter.matches(&standard_frame(0x123, &[])));
+        assert!(!frame_filter.matches(&extended_frame(0x123, &[])));
+        assert!(frame_filter.matches(&extended_frame(0x456, &[])));
+        assert!(!frame_filter.matches(&standard_frame(0x456, &[])));
+    }
+
+    #[test]
+    fn combined_filters_match_both() {
+        let frame_filter = FrameFilter::parse("std")
+            .unwrap()
+            .and(FrameFilter::parse("id < 0x200").unwrap());
+        assert_eq!(frame_filter.text, "(std) and (id < 0x200)");
+        assert!(frame_filter.matches(&standard_frame(0x100, &[])));
+        assert!(!frame_filter.matches(&standard_frame(0x300, &[])));
+        assert!(!frame_filter.matches(&extended_frame(0x100, &[])));
+    }
+
+    #[test]
+    fn reports_errors() {
+        for (text, error) in [
+            ("", "Unexpected end of expression"),
+            ("id ==", "Unexpected end of expression"),
+            ("foo", "Unknown filter 'foo'"),
+            ("id == 0xzz", "Invalid number '0xzz'"),
+            ("id == 0x123)", "Unexpected ')'"),
+            ("std and !", "Unexpected end of expression"),
+            ("(std", "Expected ')' but found the end"),
+            ("id in 0x100 0x1ff", "Expected '..' but found '511'"),
+            ("data[0 == 1", "Expected ']' but found '=='"),
+            ("data ~ 12", "Expected a quoted regex but found '12'"),
+            ("data ~ \"(\"", "Invalid payload regex \"(\""),
+            ("data ~ \"00", "Unterminated string"),
+            ("id == 0x1 $", "Unexpected character '$'"),
+        ] {
+            assert_eq!(FrameFilter::parse(text).unwrap_err(), error, "{:?}", text);
+        }
+    }
 }
@@ -0,0 +1,308 @@
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::socket::{self, sockopt, ControlMessageOwned, MsgFlags};
+use nix::sys::time::TimeSpec;
+use socketcan::nl::CanState;
+use socketcan::{
+    CanAnyFrame, CanFdSocket, CanFilter, CanInterface, CanTimestamps, Socket, SocketOptions,
+    SOF_TIMESTAMPING_OPT_CMSG, SOF_TIMESTAMPING_RAW_HARDWARE, SOF_TIMESTAMPING_RX_HARDWARE,
+    SOF_TIMESTAMPING_RX_SOFTWARE, SOF_TIMESTAMPING_SOFTWARE,
+};
+
+use std::io::{self, IoSliceMut};
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Longest time a frame source blocks waiting for a frame, so that capture
+/// threads notice when capturing is stopped
+pub const RECEIVE_TIMEOUT: Duration = Duration::from_millis(100);
+
+const CAN_MTU: usize = std::mem::size_of::<libc::can_frame>();
+const CANFD_MTU: usize = std::mem::size_of::<libc::canfd_frame>();
+
+/// A frame received from a frame source
+pub struct ReceivedFrame {
+    pub frame: CanAnyFrame,
+    /// Name of the interface the frame was received on
+    pub interface: Arc<str>,
+    /// Time at which the frame was received
+    pub timestamps: CanTimestamps,
+    /// Number of frames the source knows to have been dropped since the
+    /// previous frame it received
+    pub dropped_frames: u32,
+}
+
+/// A source of CAN frames which the `FrameCaptor` captures on its own thread.
+/// Frames from every source end up in the same captured frame state, labeled
+/// with the interface name they were received on.
+pub trait FrameSource: Send {
+    /// Names of the interfaces frames are received on, as far as they are
+    /// known before any frame has been received
+    fn interfaces(&self) -> Vec<Arc<str>>;
+
+    /// Name of the source in error messages
+    fn name(&self) -> String {
+        self.interfaces().join(", ")
+    }
+
+    /// Blocks until the next frame has been received, or returns a `TimedOut`
+    /// error if none arrived within `RECEIVE_TIMEOUT`. A source which has no
+    /// more frames to give returns an `UnexpectedEof` error.
+    fn receive(&mut self) -> io::Result<ReceivedFrame>;
+
+    /// Current state of the source's interfaces, for sources which can tell
+    fn interface_states(&self) -> Vec<(Arc<str>, InterfaceState)> {
+        vec![]
+    }
+
+    /// Reopens the source, such as after its interface was lost, see
+    /// `is_interface_lost`, or when a stopped capture is started again.
+    /// Sources which replay recorded frames resume where they stopped.
+    fn reopen(&mut self) -> io::Result<()>;
+}
+
+/// State of the network interface a source receives frames on
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum InterfaceState {
+    /// The interface is administratively down or does not exist
+    Down,
+    /// The interface is up, with the state of its CAN controller if the
+    /// driver reports one, which virtual interfaces do not
+    Up(Option<CanState>),
+}
+
+impl std::fmt::Display for InterfaceState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterfaceState::Down => write!(f, "down"),
+            InterfaceState::Up(None) => write!(f, "up"),
+            InterfaceState::Up(Some(CanState::ErrorActive)) => write!(f, "error-active"),
+            InterfaceState::Up(Some(CanState::ErrorWarning)) => write!(f, "error-warning"),
+            InterfaceState::Up(Some(CanState::ErrorPassive)) => write!(f, "error-passive"),
+            InterfaceState::Up(Some(CanState::BusOff)) => write!(f, "bus-off"),
+            InterfaceState::Up(Some(CanState::Stopped)) => write!(f, "stopped"),
+            InterfaceState::Up(Some(CanState::Sleeping)) => write!(f, "sleeping"),
+        }
+    }
+}
+
+/// Whether a receive error means the source's interface went away, such as
+/// when it is taken down or its adapter is unplugged, rather than a failure
+/// to receive a single frame. A serial port whose adapter was unplugged fails
+/// with an I/O error on every read.
+pub fn is_interface_lost(error: &io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(libc::ENETDOWN) | Some(libc::ENODEV) | Some(libc::ENXIO) | Some(libc::EIO)
+    )
+}
+
+/// Frames received on a SocketCAN interface
+pub struct SocketCanSource {
+    interface: Arc<str>,
+    rx_sock: CanFdSocket,
+    socket_filters: Vec<CanFilter>,
+    /// The kernel's count of frames dropped by the socket since it was opened
+    kernel_drop_count: u32,
+}
+
+impl SocketCanSource {
+    /// Opens an FD-capable socket which receives error frames, with kernel
+    /// receive timestamps enabled, using the adapter's hardware timestamps as
+    /// well when they are supported. The kernel reports the number of frames
+    /// dropped by the socket, such as when its receive queue overflows.
+    /// If any socket filters are given, only frames matching them are received.
+    pub fn open(can_interface: &str, socket_filters: &[CanFilter]) -> io::Result<Self> {
+        let rx_sock = CanFdSocket::open(can_interface)?;
+
+        if !socket_filters.is_empty() {
+            rx_sock.set_filters(socket_filters)?;
+        }
+
+        let mut timestamping_flags =
+            SOF_TIMESTAMPING_RX_SOFTWARE | SOF_TIMESTAMPING_SOFTWARE | SOF_TIMESTAMPING_OPT_CMSG;
+        if rx_sock.has_hw_timestamps() {
+            timestamping_flags |= SOF_TIMESTAMPING_RX_HARDWARE | SOF_TIMESTAMPING_RAW_HARDWARE;
+        }
+
+        rx_sock.set_error_filter_accept_all()?;
+        rx_sock.set_recv_timestamp(true)?;
+        rx_sock.set_timestamping(timestamping_flags)?;
+        rx_sock.set_read_timeout(RECEIVE_TIMEOUT)?;
+        socket::setsockopt(&rx_sock, sockopt::RxqOvfl, &1)?;
+
+        Ok(Self {
+            interface: can_interface.into(),
+            rx_sock,
+            socket_filters: socket_filters.to_vec(),
+            kernel_drop_count: 0,
+        })
+    }
+}
+
+impl FrameSource for SocketCanSource {
+    fn interfaces(&self) -> Vec<Arc<str>> {
+        vec![Arc::clone(&self.interface)]
+    }
+
+    fn interface_states(&self) -> Vec<(Arc<str>, InterfaceState)> {
+        // An interface which can not be looked up has been removed, such as
+        // a USB adapter which was unplugged
+        let state = CanInterface::open(&self.interface)
+            .ok()
+            .and_then(|can_interface| can_interface.details().ok())
+            .filter(|details| details.is_up)
+            .map_or(InterfaceState::Down, |details| {
+                InterfaceState::Up(details.can.state)
+            });
+
+        vec![(Arc::clone(&self.interface), state)]
+    }
+
+    /// The old socket is of no use once its interface went down, as the
+    /// kernel unbinds it if the interface is removed, and holds frames queued
+    /// while capturing was stopped, so a new one is opened with the same settings
+    fn reopen(&mut self) -> io::Result<()> {
+        *self = Self::open(&self.interface, &self.socket_filters)?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<ReceivedFrame> {
+        let mut frame_buf = [0u8; CANFD_MTU];
+        let mut cmsg_buf = nix::cmsg_space!(TimeSpec, [TimeSpec; 3], u32);
+
+        let mut timestamps = CanTimestamps::default();
+        let mut kernel_drop_count = self.kernel_drop_count;
+
+        // The frame is stamped by the kernel on arrival, so time spent
+        // processing it afterwards does not skew the timestamp
+        let frame_len = {
+            let mut iov = [IoSliceMut::new(&mut frame_buf)];
+            let msg = match socket::recvmsg::<()>(
+                self.rx_sock.as_raw_fd(),
+                &mut iov,
+                Some(&mut cmsg_buf),
+                MsgFlags::empty(),
+            ) {
+                Ok(msg) => msg,
+                Err(Errno::EAGAIN) => return Err(io::ErrorKind::TimedOut.into()),
+                Err(e) => return Err(e.into()),
+            };
+
+            for cmsg in msg.cmsgs()? {
+                match cmsg {
+                    ControlMessageOwned::ScmTimestampns(timestamp) => {
+                        timestamps.socket = Some(UNIX_EPOCH + Duration::from(timestamp));
+                    }
+                    // Timestamps which were not requested are left zeroed
+                    ControlMessageOwned::ScmTimestampsns(timestamping) => {
+                        if timestamping.system != TimeSpec::new(0, 0) {
+                            timestamps.sw = Some(UNIX_EPOCH + Duration::from(timestamping.system));
+                        }
+                        if timestamping.hw_raw != TimeSpec::new(0, 0) {
+                            timestamps.hw = Some(Duration::from(timestamping.hw_raw));
+                        }
+                    }
+                    ControlMessageOwned::RxqOvfl(drop_count) => kernel_drop_count = drop_count,
+                    _ => {}
+                }
+            }
+
+            msg.bytes
+        };
+
+        // SAFETY: the buffer holds at least as many bytes as the frame struct
+        // read from it, and every bit pattern is valid for the integer fields
+        // of the frame structs
+        let frame: CanAnyFrame = match frame_len {
+            CAN_MTU => {
+                unsafe { std::ptr::read_unaligned(frame_buf.as_ptr().cast::<libc::can_frame>()) }
+                    .into()
+            }
+            CANFD_MTU => {
+                unsafe { std::ptr::read_unaligned(frame_buf.as_ptr().cast::<libc::canfd_frame>()) }
+                    .into()
+            }
+            _ => return Err(io::ErrorKind::InvalidData.into()),
+        };
+
+        let dropped_frames = kernel_drop_count.wrapping_sub(self.kernel_drop_count);
+        self.kernel_drop_count = kernel_drop_count;
+
+        Ok(ReceivedFrame {
+            frame,
+            interface: Arc::clone(&self.interface),
+            timestamps,
+            dropped_frames,
+        })
+    }
+}
+
+/// Frames queued in memory, standing in for an interface in tests. Clones
+/// share the queue, so a test can keep a clone to feed frames to a source
+/// which is being captured. Like a socket, the source skips frames queued
+/// while its capture was stopped when it is reopened.
+#[cfg(test)]
+#[derive(Clone)]
+pub struct MemorySource {
+    interface: Arc<str>,
+    frames: Arc<std::sync::Mutex<std::collections::VecDeque<io::Result<CanAnyFrame>>>>,
+    reopen_count: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+#[cfg(test)]
+impl MemorySource {
+    pub fn new(interface: &str) -> Self {
+        Self {
+            interface: interface.into(),
+            frames: Default::default(),
+            reopen_count: Default::default(),
+        }
+    }
+
+    pub fn push(&self, frame: CanAnyFrame) {
+        self.frames.lock().unwrap().push_back(Ok(frame));
+    }
+
+    /// Makes the source fail to receive once the frames before it are taken
+    pub fn push_error(&self, error: io::Error) {
+        self.frames.lock().unwrap().push_back(Err(error));
+    }
+
+    pub fn get_reopen_count(&self) -> usize {
+        self.reopen_count.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+impl FrameSource for MemorySource {
+    fn interfaces(&self) -> Vec<Arc<str>> {
+        vec![Arc::clone(&self.interface)]
+    }
+
+    fn receive(&mut self) -> io::Result<ReceivedFrame> {
+        let Some(frame) = self.frames.lock().unwrap().pop_front() else {
+            // Waiting a little keeps an idle capture thread from spinning
+            std::thread::sleep(Duration::from_millis(5));
+            return Err(io::ErrorKind::TimedOut.into());
+        };
+
+        Ok(ReceivedFrame {
+            frame: frame?,
+            interface: Arc::clone(&self.interface),
+            timestamps: CanTimestamps {
+                socket: Some(std::time::SystemTime::now()),
+                ..Default::default()
+            },
+            dropped_frames: 0,
+        })
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        self.frames.lock().unwrap().clear();
+        self.reopen_count
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Ok(())
+    }
+}
@@ -1,21 +1,40 @@
+mod baseline;
+mod draw_bit_heatmap;
+mod draw_frame_diff;
+mod draw_frame_inspector;
 mod draw_frame_table;
+mod error_frame;
 mod frame;
 mod frame_filter;
+mod frame_source;
+mod message_timeout;
+mod replay;
+mod slcan;
 mod ui;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 use clap::Parser;
 use crossterm::event::{self, KeyCode};
 use crossterm::event::{Event, KeyEventKind};
 
-use frame_filter::FrameIdFilter;
+use frame_filter::FrameFilter;
 use ratatui::{prelude::*, widgets::*};
+use socketcan::CanFilter;
 
+use std::collections::{HashMap, VecDeque};
 use std::io;
 use std::time::{Duration, Instant};
 
-use crate::frame::FrameCaptor;
+use crate::baseline::Baseline;
+use crate::frame::{
+    format_timestamp, get_data_len, get_raw_id, CapturedFrameState, CountedFrame, FrameCaptor,
+    FrameHistoryLimit, FrameSetKey, TimestampedFrame,
+};
+use crate::frame_source::{FrameSource, SocketCanSource};
+use crate::message_timeout::MessageTimeouts;
+use crate::replay::ReplaySource;
+use crate::slcan::SlcanSource;
 use crate::ui::ui;
 
 const APP_TITLE: &str = "CAN VIEWER TUI";
@@ -23,23 +42,81 @@ const DEFAULT_MAX_FRAMES_PER_SECOND: u32 = 1000;
 const APP_TICK_RATE_MILLISECONDS: u64 = 100;
 // Constant-size table to avoid performance degrading as more frames are captured
 const APP_FRAMES_DISPLAYED_MAX_DEFAULT: usize = 500;
+const DEFAULT_SLCAN_BITRATE: u32 = 500_000;
+const DEFAULT_REPLAY_SPEED: f64 = 1.0;
+const DEFAULT_TIMEOUT_PERIOD_MULTIPLE: f64 = 3.0;
+const DEFAULT_CHANGE_HIGHLIGHT_MILLISECONDS: u64 = 2000;
 
 #[derive(Parser, Debug)]
 #[command(arg_required_else_help = true)]
+#[command(group(clap::ArgGroup::new("frame_source").required(true).multiple(true).args(["can_interface", "serial", "replay"])))]
+#[command(group(clap::ArgGroup::new("history_limit").multiple(true).args(["history_max_frames", "history_max_megabytes"])))]
 struct Args {
-    /// Which can interface to listen to
-    #[arg(short, long)]
-    can_interface: String,
+    /// Which can interfaces to listen to, each captured on its own thread
+    #[arg(short, long, num_args = 1.., value_delimiter = ' ')]
+    can_interface: Vec<String>,
+    /// Serial device of an slcan (Lawicel) USB-serial CAN adapter to listen to
+    #[arg(long)]
+    serial: Option<String>,
+    /// Bitrate of the CAN bus the slcan adapter is connected to
+    #[arg(long, default_value_t = DEFAULT_SLCAN_BITRATE, requires = "serial")]
+    bitrate: u32,
+    /// Timestamp frames with the slcan adapter's clock rather than on arrival
+    #[arg(long, requires = "serial")]
+    slcan_timestamps: bool,
+    /// Log file recorded with `candump -l` to replay, with its original timestamps
+    #[arg(long)]
+    replay: Option<String>,
+    /// Replay speed relative to the original timing, such as 0.5 or 2.
+    /// A speed of 0 replays the log as fast as possible
+    #[arg(long, default_value_t = DEFAULT_REPLAY_SPEED, requires = "replay")]
+    replay_speed: f64,
     /// CAN frame ID's to include in the resulting frame list, as hexadecimal values.
     /// If no ID's are given, all frames are included
     #[arg(short, long, default_value = None, value_parser, num_args = 1.., value_delimiter = ' ')]
     filter_frame_ids: Option<Vec<String>>,
+    /// Filter expression on the frames shown, such as
+    /// "id in 0x100..0x1ff and not ext and data[2] & 0x80 and data ~ \"^00ff\"".
+    /// See the README for the full expression language
+    #[arg(long, default_value = None, value_parser = FrameFilter::parse)]
+    filter: Option<FrameFilter>,
+    /// CAN ID and mask pairs to filter frames on, as hexadecimal <ID>:<MASK> values.
+    /// A frame is included if its ID, masked with MASK, equals ID masked with MASK
+    #[arg(long, default_value = None, requires = "kernel_filter", num_args = 1.., value_delimiter = ' ', value_parser = parse_filter_id_mask)]
+    filter_id_masks: Option<Vec<(u32, u32)>>,
+    /// Apply the frame filters on the CAN sockets, so that frames which are filtered
+    /// out never reach can-viewer-tui and are not included in the frame counters
+    #[arg(short, long, conflicts_with_all = ["serial", "replay"])]
+    kernel_filter: bool,
     /// Max-value of the frames per second graph
     #[arg(short, long, default_value_t = DEFAULT_MAX_FRAMES_PER_SECOND)]
     max_frames_per_second_graph: u32,
     /// Maximum number of frames shown in the table at the same time
     #[arg(long, default_value_t = APP_FRAMES_DISPLAYED_MAX_DEFAULT)]
     frame_table_size: usize,
+    /// Maximum number of frames kept in memory, the oldest frames being evicted first.
+    /// If no limit is given, all captured frames are kept
+    #[arg(long)]
+    history_max_frames: Option<usize>,
+    /// Maximum memory used by the frames kept in memory, in megabytes
+    #[arg(long)]
+    history_max_megabytes: Option<usize>,
+    /// File to write evicted frames to in candump log format, instead of discarding them.
+    /// Requires a history limit, as no frames are evicted without one
+    #[arg(long, requires = "history_limit")]
+    history_spill_file: Option<String>,
+    /// Number of its periods a CAN ID may go without a frame before it is flagged as
+    /// timed out. A multiple of 0 turns off timeout detection
+    #[arg(long, default_value_t = DEFAULT_TIMEOUT_PERIOD_MULTIPLE)]
+    timeout_period_multiple: f64,
+    /// Expected periods of CAN IDs, as <ID>:<MILLISECONDS> values with a hexadecimal ID.
+    /// Other IDs time out based on the periods observed on the bus
+    #[arg(long, default_value = None, num_args = 1.., value_delimiter = ' ', value_parser = parse_message_period)]
+    message_periods: Option<Vec<(u32, Duration)>>,
+    /// Milliseconds over which the highlighting of changed payload bytes in the frame
+    /// set fades. A time of 0 turns off highlighting
+    #[arg(long, default_value_t = DEFAULT_CHANGE_HIGHLIGHT_MILLISECONDS)]
+    change_highlight_ms: u64,
 }
 
 pub enum FrameView {
@@ -47,21 +124,94 @@ pub enum FrameView {
     FrameSet,
 }
 
+/// Snapshot of the frame tables taken when the display was frozen. Frames keep
+/// being captured meanwhile, but are not shown until the display resumes.
+pub struct FrozenDisplay {
+    /// Frame number of the first frame captured after freezing, from which on
+    /// frames are left out of the frame list
+    pub next_frame_number: u64,
+    pub frame_set: HashMap<FrameSetKey, CountedFrame>,
+    /// Changed payload bytes stay highlighted as they were at this time
+    pub frozen_at: Instant,
+}
+
+/// What the text typed in at the input prompt is for
+#[derive(Clone, Copy)]
+pub enum InputPrompt {
+    SwitchInterfaces,
+    GoToFrame,
+    Filter,
+}
+
+impl InputPrompt {
+    pub fn get_title(&self) -> &'static str {
+        match self {
+            InputPrompt::SwitchInterfaces => {
+                " Switch to CAN Interfaces (Enter to Switch, Esc to Cancel) "
+            }
+            InputPrompt::GoToFrame => {
+                " Go to Frame # or Timestamp, such as 42 or 1700000000.250000 (Enter to Go, Esc to Cancel) "
+            }
+            InputPrompt::Filter => " Filter Expression (Enter to Apply, Esc to Cancel) ",
+        }
+    }
+}
+
+pub struct TextInput {
+    pub prompt: InputPrompt,
+    pub text: String,
+}
+
 /// Function pointer to function for drawing the main table of captured frames
 type DrawFrameTableCallback = fn(&mut ratatui::Frame, app: &mut App, area: Rect);
 
 pub struct App<'a> {
     pub frame_view: FrameView,
     pub table_state: TableState,
+    /// The selected frame of the frame list, which stays selected as newer
+    /// frames are added above it
+    pub selected_frame_number: Option<u64>,
+    /// The frame at the top of the frame list, or `None` to follow the live
+    /// frames
+    pub top_frame_number: Option<u64>,
+    /// Number of rows which fit in the frame table when it was last drawn
+    pub frame_table_page_size: usize,
+    /// Frame of the frame list which the selected frame is compared against.
+    /// It is kept when it is evicted or the frame info is cleared.
+    pub marked_frame: Option<TimestampedFrame>,
+    /// The selected frame of the frame set
+    pub selected_frame_set_key: Option<FrameSetKey>,
     pub title: &'a str,
     pub frames_per_second_max: u32,
-    pub frame_id_filter: Option<FrameIdFilter>,
+    pub frame_filter: Option<FrameFilter>,
     pub frame_captor: FrameCaptor,
     pub enhanced_graphics: bool,
     pub row_color_main: Color,
     pub row_color_alt: Color,
     pub frames_displayed_max: usize,
     pub draw_frame_table: DrawFrameTableCallback,
+    /// Socket filters applied to interfaces switched to at runtime
+    pub socket_filters: Vec<CanFilter>,
+    /// Text being typed in at the input prompt, while it is open
+    pub text_input: Option<TextInput>,
+    /// Outcome of the last action, if it failed
+    pub status_message: Option<String>,
+    pub frozen_display: Option<FrozenDisplay>,
+    /// Time over which the highlighting of changed payload bytes fades
+    pub change_highlight_duration: Duration,
+    /// Whether the bit heatmap of the selected ID is shown next to the frame set
+    pub show_bit_heatmap: bool,
+    /// First payload byte shown in the bit heatmap, for payloads which do not
+    /// fit in it
+    pub bit_heatmap_scroll: usize,
+    /// Whether the details of the selected frame are shown next to the table
+    pub show_frame_inspector: bool,
+    /// First row of the payload interpretations shown in the frame inspector
+    pub frame_inspector_scroll: usize,
+    /// Snapshot of the IDs and bit values seen while the bus was idle
+    pub baseline: Option<Baseline>,
+    /// Whether only the frames which deviate from the baseline are shown
+    pub show_changes_only: bool,
 }
 
 impl<'a> App<'a> {
@@ -74,62 +224,623 @@ impl<'a> App<'a> {
     ) -> Self {
         App {
             frame_view: FrameView::FrameList,
-            table_state: TableState::default().with_selected(0),
+            table_state: TableState::default(),
+            selected_frame_number: None,
+            top_frame_number: None,
+            frame_table_page_size: frames_displayed_max,
+            marked_frame: None,
+            selected_frame_set_key: None,
             title,
             frames_per_second_max,
-            frame_id_filter: None,
+            frame_filter: None,
             frame_captor,
             enhanced_graphics,
             row_color_main: Color::White,
             row_color_alt: Color::Gray,
             frames_displayed_max,
             draw_frame_table: draw_frame_table::draw_timestamped_frame_table,
+            socket_filters: vec![],
+            text_input: None,
+            status_message: None,
+            frozen_display: None,
+            change_highlight_duration: Duration::ZERO,
+            show_bit_heatmap: false,
+            bit_heatmap_scroll: 0,
+            show_frame_inspector: false,
+            frame_inspector_scroll: 0,
+            baseline: None,
+            show_changes_only: false,
+        }
+    }
+
+    /// The frame list, along with the end of the part of it which can be
+    /// shown, as frames captured after the display was frozen are left out
+    fn get_frame_history(&self) -> (&VecDeque<TimestampedFrame>, usize) {
+        let captured_frames_list = &self.frame_captor.get_captured_frames().captured_frames_list;
+
+        // Frames captured after the display was frozen are at the end of the list
+        let history_end = match &self.frozen_display {
+            Some(frozen_display) => captured_frames_list
+                .partition_point(|frame| frame.frame_number < frozen_display.next_frame_number),
+            None => captured_frames_list.len(),
+        };
+
+        (captured_frames_list, history_end)
+    }
+
+    fn is_frame_displayed(&self, frame: &TimestampedFrame) -> bool {
+        let is_included = match &self.frame_filter {
+            Some(frame_filter) => frame_filter.matches(&frame.frame),
+            None => true,
+        };
+
+        is_included
+            && self
+                .get_changes_only_baseline()
+                .is_none_or(|baseline| baseline.is_frame_deviating(frame))
+    }
+
+    /// The baseline which frames are compared against, while only the frames
+    /// which deviate from it are shown
+    pub fn get_changes_only_baseline(&self) -> Option<&Baseline> {
+        self.baseline.as_ref().filter(|_| self.show_changes_only)
+    }
+
+    /// Number of the frame `rows` displayed rows newer than the given frame,
+    /// or of the newest displayed frame if there are not as many rows
+    fn get_newer_displayed_frame(&self, frame_number: u64, rows: usize) -> Option<u64> {
+        let (frame_history, history_end) = self.get_frame_history();
+        let index = frame_history.partition_point(|frame| frame.frame_number <= frame_number);
+
+        frame_history
+            .range(index.min(history_end)..history_end)
+            .filter(|frame| self.is_frame_displayed(frame))
+            .take(rows)
+            .last()
+            .map(|frame| frame.frame_number)
+    }
+
+    /// Number of the frame `rows` displayed rows older than the given frame,
+    /// or of the oldest displayed frame if there are not as many rows
+    fn get_older_displayed_frame(&self, frame_number: u64, rows: usize) -> Option<u64> {
+        let (frame_history, history_end) = self.get_frame_history();
+        let index = frame_history.partition_point(|frame| frame.frame_number < frame_number);
+
+        frame_history
+            .range(..index.min(history_end))
+            .rev()
+            .filter(|frame| self.is_frame_displayed(frame))
+            .take(rows)
+            .last()
+            .map(|frame| frame.frame_number)
+    }
+
+    /// Number of the frame at the top of the last page of the history
+    fn get_oldest_page_top(&self) -> Option<u64> {
+        let (frame_history, history_end) = self.get_frame_history();
+
+        frame_history
+            .range(..history_end)
+            .filter(|frame| self.is_frame_displayed(frame))
+            .take(self.frame_table_page_size)
+            .last()
+            .map(|frame| frame.frame_number)
+    }
+
+    /// Page of frames shown in the frame list, newest first, starting at the
+    /// top frame or at the newest frame when following the live frames. Only
+    /// the frames of a page are ever gathered, however long the history is.
+    pub fn get_displayed_frames(&self, max_frames: usize) -> Vec<&TimestampedFrame> {
+        let (frame_history, history_end) = self.get_frame_history();
+
+        let page_end = match self.top_frame_number {
+            Some(top_frame_number) => frame_history
+                .partition_point(|frame| frame.frame_number <= top_frame_number)
+                .min(history_end),
+            None => history_end,
+        };
+
+        // The top frame has been evicted, along with every frame of its page
+        let page_end = match page_end {
+            0 => self.get_oldest_page_top().map_or(0, |oldest_page_top| {
+                frame_history.partition_point(|frame| frame.frame_number <= oldest_page_top)
+            }),
+            _ => page_end,
+        };
+
+        frame_history
+            .range(..page_end)
+            .rev()
+            .filter(|frame| self.is_frame_displayed(frame))
+            .take(max_frames.min(self.frames_displayed_max))
+            .collect()
+    }
+
+    fn get_displayed_frame_numbers(&self) -> Vec<u64> {
+        self.get_displayed_frames(self.frame_table_page_size)
+            .iter()
+            .map(|frame| frame.frame_number)
+            .collect()
+    }
+
+    /// The frame set as it is shown, which is the snapshot taken when the
+    /// display was frozen while it is
+    fn get_frame_set(&self) -> &HashMap<FrameSetKey, CountedFrame> {
+        match &self.frozen_display {
+            Some(frozen_display) => &frozen_display.frame_set,
+            None => &self.frame_captor.get_captured_frames().captured_frames_set,
         }
     }
 
-    fn get_frame_table_len(&mut self) -> usize {
+    /// The frame selected in the frame list, unless it has been evicted
+    pub fn get_selected_frame(&self) -> Option<&TimestampedFrame> {
+        let selected_frame_number = self.selected_frame_number?;
+        let (frame_history, history_end) = self.get_frame_history();
+        let index =
+            frame_history.partition_point(|frame| frame.frame_number < selected_frame_number);
+
+        frame_history
+            .range(..history_end)
+            .nth(index)
+            .filter(|frame| frame.frame_number == selected_frame_number)
+    }
+
+    /// Marks the selected frame of the frame list to compare other frames
+    /// against, or removes the mark if the selected frame is already marked
+    /// or no frame is selected
+    pub fn toggle_marked_frame(&mut self) {
+        let selected_frame = self.get_selected_frame();
+        let is_marked = match (&self.marked_frame, selected_frame) {
+            (Some(marked_frame), Some(selected_frame)) => {
+                marked_frame.frame_number == selected_frame.frame_number
+            }
+            _ => false,
+        };
+
+        self.marked_frame = match is_marked {
+            true => None,
+            false => selected_frame.cloned(),
+        };
+    }
+
+    /// Latest frame of the ID selected in the frame set
+    pub fn get_selected_counted_frame(&self) -> Option<&CountedFrame> {
+        self.get_frame_set()
+            .get(self.selected_frame_set_key.as_ref()?)
+    }
+
+    /// Frames shown in the frame set, ordered by interface and ID so that
+    /// rows keep their place as new IDs show up. The filter is applied on
+    /// the latest frame of each ID.
+    pub fn get_displayed_frame_set(&self) -> Vec<(&FrameSetKey, &CountedFrame)> {
+        let baseline = self.get_changes_only_baseline();
+        let mut frames = self
+            .get_frame_set()
+            .iter()
+            .filter(|(_, frame)| {
+                self.frame_filter
+                    .as_ref()
+                    .is_none_or(|frame_filter| frame_filter.matches(&frame.frame))
+            })
+            .filter(|(key, frame)| {
+                baseline.is_none_or(|baseline| baseline.is_deviating(key, frame))
+            })
+            .collect::<Vec<_>>();
+        frames.sort_unstable_by_key(|((interface, id), _)| {
+            (
+                std::sync::Arc::clone(interface),
+                get_raw_id(*id),
+                matches!(id, embedded_can::Id::Extended(_)),
+            )
+        });
+        frames
+    }
+
+    /// Row of the selected frame among the displayed rows, if it is displayed
+    pub fn get_selected_row(&self) -> Option<usize> {
         match self.frame_view {
-            FrameView::FrameList => self.frame_captor.get_captured_frames_list_len(),
-            FrameView::FrameSet => self.frame_captor.get_captured_frames_set_len(),
+            FrameView::FrameList => {
+                let selected_frame_number = self.selected_frame_number?;
+                self.get_displayed_frame_numbers()
+                    .iter()
+                    .position(|frame_number| *frame_number == selected_frame_number)
+            }
+            FrameView::FrameSet => {
+                let selected_frame_set_key = self.selected_frame_set_key.as_ref()?;
+                self.get_displayed_frame_set()
+                    .iter()
+                    .position(|(key, _)| *key == selected_frame_set_key)
+            }
+        }
+    }
+
+    /// Selects the frame set row picked by `pick_row`, given the row of the
+    /// selected frame, if any, and the number of rows
+    fn select_frame_set_row(&mut self, pick_row: impl Fn(Option<usize>, usize) -> usize) {
+        let selected_row = self.get_selected_row();
+        let frames = self.get_displayed_frame_set();
+        if frames.is_empty() {
+            return;
+        }
+
+        let row = pick_row(selected_row, frames.len()).min(frames.len() - 1);
+        self.selected_frame_set_key = Some(frames[row].0.clone());
+    }
+
+    /// Selects the given frame of the frame list, scrolling the list as
+    /// little as possible to bring it into view. Selecting a frame stops
+    /// following the live frames, so that the selected frame keeps its place.
+    fn select_frame(&mut self, frame_number: u64) {
+        self.selected_frame_number = Some(frame_number);
+
+        let displayed_frame_numbers = self.get_displayed_frame_numbers();
+        if displayed_frame_numbers.contains(&frame_number) {
+            self.top_frame_number = self
+                .top_frame_number
+                .or(displayed_frame_numbers.first().copied());
+        } else if displayed_frame_numbers
+            .first()
+            .is_none_or(|top_frame_number| frame_number > *top_frame_number)
+        {
+            self.top_frame_number = Some(frame_number);
+        } else {
+            self.top_frame_number = self
+                .get_newer_displayed_frame(frame_number, self.frame_table_page_size - 1)
+                .or(Some(frame_number));
         }
     }
 
     pub fn select_next_msg(&mut self) {
-        let i = match self.table_state.selected() {
-            Some(i) => {
-                let len = self.get_frame_table_len();
-                if i >= len - 1 {
-                    0
-                } else {
-                    i + 1
+        if let FrameView::FrameSet = self.frame_view {
+            return self.select_frame_set_row(|selected_row, len| match selected_row {
+                Some(row) if row + 1 < len => row + 1,
+                _ => 0,
+            });
+        }
+
+        let displayed_frame_numbers = self.get_displayed_frame_numbers();
+        let next_frame_number = match self.selected_frame_number {
+            // Wraps around to the newest frame past the oldest one
+            Some(frame_number) => self
+                .get_older_displayed_frame(frame_number, 1)
+                .or_else(|| self.get_newer_displayed_frame(frame_number, usize::MAX)),
+            None => displayed_frame_numbers.first().copied(),
+        };
+
+        if let Some(next_frame_number) = next_frame_number {
+            self.select_frame(next_frame_number);
+        }
+    }
+
+    pub fn select_prev_msg(&mut self) {
+        if let FrameView::FrameSet = self.frame_view {
+            return self.select_frame_set_row(|selected_row, len| match selected_row {
+                Some(row) if row > 0 => row - 1,
+                _ => len - 1,
+            });
+        }
+
+        let displayed_frame_numbers = self.get_displayed_frame_numbers();
+        let prev_frame_number = match self.selected_frame_number {
+            // Wraps around to the oldest frame past the newest one
+            Some(frame_number) => self
+                .get_newer_displayed_frame(frame_number, 1)
+                .or_else(|| self.get_older_displayed_frame(frame_number, usize::MAX)),
+            None => displayed_frame_numbers.last().copied(),
+        };
+
+        if let Some(prev_frame_number) = prev_frame_number {
+            self.select_frame(prev_frame_number);
+        }
+    }
+
+    /// Scrolls a page towards the older frames, keeping the selection on the
+    /// same row
+    pub fn page_down(&mut self) {
+        if let FrameView::FrameSet = self.frame_view {
+            let page_size = self.frame_table_page_size;
+            return self
+                .select_frame_set_row(|selected_row, _| selected_row.unwrap_or(0) + page_size);
+        }
+
+        let selected_row = self.get_selected_row();
+        let displayed_frame_numbers = self.get_displayed_frame_numbers();
+        let Some(bottom_frame_number) = displayed_frame_numbers.last() else {
+            return;
+        };
+
+        self.top_frame_number = self
+            .get_older_displayed_frame(*bottom_frame_number, 1)
+            .or_else(|| self.get_oldest_page_top());
+        self.select_same_row(selected_row);
+    }
+
+    /// Scrolls a page towards the newer frames, keeping the selection on the
+    /// same row
+    pub fn page_up(&mut self) {
+        if let FrameView::FrameSet = self.frame_view {
+            let page_size = self.frame_table_page_size;
+            return self.select_frame_set_row(|selected_row, _| {
+                selected_row.unwrap_or(0).saturating_sub(page_size)
+            });
+        }
+
+        let selected_row = self.get_selected_row();
+        let Some(top_frame_number) = self.top_frame_number else {
+            return;
+        };
+
+        self.top_frame_number = self
+            .get_newer_displayed_frame(top_frame_number, self.frame_table_page_size)
+            .or(Some(top_frame_number));
+        self.select_same_row(selected_row);
+    }
+
+    /// Scrolls to the oldest frames
+    pub fn scroll_to_oldest(&mut self) {
+        if let FrameView::FrameSet = self.frame_view {
+            return self.select_frame_set_row(|_, len| len - 1);
+        }
+
+        let selected_row = self.get_selected_row();
+        self.top_frame_number = self.get_oldest_page_top();
+        self.select_same_row(selected_row);
+    }
+
+    /// Selects the frame on the given row after scrolling, if a frame was
+    /// selected on the page scrolled away from
+    fn select_same_row(&mut self, selected_row: Option<usize>) {
+        if let Some(selected_row) = selected_row {
+            let displayed_frame_numbers = self.get_displayed_frame_numbers();
+            self.selected_frame_number = displayed_frame_numbers
+                .get(selected_row)
+                .or(displayed_frame_numbers.last())
+                .copied();
+        }
+    }
+
+    /// Clears the selection and scrolls to the top, so that the table follows
+    /// the latest frames again
+    pub fn select_latest_msg(&mut self) {
+        match self.frame_view {
+            FrameView::FrameList => {
+                self.selected_frame_number = None;
+                self.top_frame_number = None;
+            }
+            FrameView::FrameSet => self.selected_frame_set_key = None,
+        }
+        self.table_state = TableState::default();
+    }
+
+    /// Selects the frame with the given frame number, or the first frame
+    /// received at or after the given timestamp in seconds, in the frame list.
+    /// Targets outside of the frames in the history are refused.
+    pub fn go_to_frame(&mut self, target: &str) -> std::result::Result<(), String> {
+        let (frame_history, history_end) = self.get_frame_history();
+        let (Some(first_frame), Some(last_frame)) = (
+            frame_history.front(),
+            history_end
+                .checked_sub(1)
+                .and_then(|last| frame_history.get(last)),
+        ) else {
+            return Err(String::from("No frames to go to"));
+        };
+
+        let index = match parse_go_to_target(target)? {
+            GoToTarget::FrameNumber(frame_number) => {
+                if !(first_frame.frame_number..=last_frame.frame_number).contains(&frame_number) {
+                    return Err(format!(
+                        "Frame #{} is not in the history, which holds frames #{} to #{}",
+                        frame_number, first_frame.frame_number, last_frame.frame_number
+                    ));
+                }
+                frame_history.partition_point(|frame| frame.frame_number < frame_number)
+            }
+            GoToTarget::Timestamp(timestamp) => {
+                let (first_timestamp, last_timestamp) = (
+                    first_frame.get_timestamp().as_secs_f64(),
+                    last_frame.get_timestamp().as_secs_f64(),
+                );
+                if !(first_timestamp..=last_timestamp).contains(&timestamp) {
+                    return Err(format!(
+                        "{} s is not in the history, which spans {} s to {} s",
+                        timestamp,
+                        format_timestamp(first_frame.get_timestamp()),
+                        format_timestamp(last_frame.get_timestamp())
+                    ));
                 }
+                // Frames are in capture order, which follows their timestamps
+                // closely enough to search them by timestamp
+                frame_history
+                    .partition_point(|frame| frame.get_timestamp().as_secs_f64() < timestamp)
             }
-            None => 0,
         };
-        self.table_state.select(Some(i));
+
+        // The closest displayed frame, preferring newer frames
+        let frame_number = frame_history
+            .range(index.min(history_end)..history_end)
+            .chain(frame_history.range(..index.min(history_end)).rev())
+            .find(|frame| self.is_frame_displayed(frame))
+            .map(|frame| frame.frame_number)
+            .ok_or_else(|| format!("No frame to go to at {}", target))?;
+
+        if let FrameView::FrameSet = self.frame_view {
+            self.toggle_frame_table_ui();
+        }
+        self.top_frame_number = Some(frame_number);
+        self.selected_frame_number = Some(frame_number);
+
+        Ok(())
     }
 
-    pub fn select_prev_msg(&mut self) {
-        let len = self.get_frame_table_len();
-        let i = match self.table_state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    len - 1
-                } else {
-                    i - 1
+    /// Scrolls the bit heatmap by the given number of payload bytes, keeping
+    /// at least the last byte of the selected ID's payload in view
+    pub fn scroll_bit_heatmap(&mut self, bytes: isize) {
+        let byte_count = self
+            .get_selected_counted_frame()
+            .map_or(0, |frame| get_data_len(&frame.frame));
+        self.bit_heatmap_scroll = self
+            .bit_heatmap_scroll
+            .saturating_add_signed(bytes)
+            .min(byte_count.saturating_sub(1));
+    }
+
+    /// Scrolls the payload interpretations of the frame inspector by the
+    /// given number of rows, keeping at least the last row in view
+    pub fn scroll_frame_inspector(&mut self, rows: isize) {
+        let inspected_frame = match self.frame_view {
+            FrameView::FrameList => self.get_selected_frame().map(|frame| &frame.frame),
+            FrameView::FrameSet => self.get_selected_counted_frame().map(|frame| &frame.frame),
+        };
+        let row_count = inspected_frame.map_or(0, |frame| {
+            draw_frame_inspector::get_interpretation_row_count(get_data_len(frame))
+        });
+        self.frame_inspector_scroll = self
+            .frame_inspector_scroll
+            .saturating_add_signed(rows)
+            .min(row_count.saturating_sub(1));
+    }
+
+    /// Freezes the frame tables as they are now, or resumes showing the live
+    /// frames, jumping to the latest frame
+    pub fn toggle_frozen_display(&mut self) {
+        match self.frozen_display {
+            Some(_) => {
+                self.frozen_display = None;
+                self.select_latest_msg();
+            }
+            None => {
+                self.frozen_display = Some(FrozenDisplay {
+                    next_frame_number: self.frame_captor.get_next_frame_number(),
+                    frame_set: self
+                        .frame_captor
+                        .get_captured_frames()
+                        .captured_frames_set
+                        .clone(),
+                    frozen_at: Instant::now(),
+                })
+            }
+        }
+    }
+
+    /// Time at which the displayed frames are shown, which stands still while
+    /// the display is frozen
+    pub fn get_display_time(&self) -> Instant {
+        self.frozen_display
+            .as_ref()
+            .map_or_else(Instant::now, |frozen_display| frozen_display.frozen_at)
+    }
+
+    /// Records the IDs and bit values seen so far as the baseline, replacing
+    /// any earlier baseline. The baseline is kept when the captured frames
+    /// are cleared, so that only frames captured afterwards are compared.
+    pub fn record_baseline(&mut self) {
+        self.baseline = Some(Baseline::record(
+            &self.frame_captor.get_captured_frames().captured_frames_set,
+        ));
+    }
+
+    /// Shows only the frames which deviate from the baseline, or all frames
+    pub fn toggle_changes_only(&mut self) {
+        if self.baseline.is_none() {
+            self.status_message = Some(String::from(
+                "No baseline recorded, press <R> to record one",
+            ));
+            return;
+        }
+
+        self.status_message = None;
+        self.show_changes_only = !self.show_changes_only;
+    }
+
+    /// Frames captured since the display was frozen, if it is
+    pub fn get_new_frames_since_freeze(&self) -> Option<u64> {
+        self.frozen_display.as_ref().map(|frozen_display| {
+            self.frame_captor.get_next_frame_number() - frozen_display.next_frame_number
+        })
+    }
+
+    pub fn toggle_capture(&mut self) {
+        self.status_message = None;
+
+        if self.frame_captor.is_capturing() {
+            self.frame_captor.stop_capture();
+        } else if let Err(e) = self.frame_captor.start_capture() {
+            self.status_message = Some(format!("Failed to start capture: {}", e));
+        }
+    }
+
+    pub fn start_text_input(&mut self, prompt: InputPrompt) {
+        self.status_message = None;
+
+        // The current filter is edited rather than typed in again
+        let text = match (prompt, &self.frame_filter) {
+            (InputPrompt::Filter, Some(frame_filter)) => frame_filter.text.clone(),
+            _ => String::new(),
+        };
+        self.text_input = Some(TextInput { prompt, text });
+    }
+
+    /// Closes the input prompt and acts on the text typed in
+    pub fn submit_text_input(&mut self) {
+        let Some(text_input) = self.text_input.take() else {
+            return;
+        };
+
+        match text_input.prompt {
+            InputPrompt::SwitchInterfaces => self.switch_interfaces(&text_input.text),
+            InputPrompt::GoToFrame => {
+                if let Err(e) = self.go_to_frame(text_input.text.trim()) {
+                    self.status_message = Some(e);
                 }
             }
-            None => len - 1,
+            InputPrompt::Filter => {
+                if let Err(e) = self.set_frame_filter(&text_input.text) {
+                    self.status_message = Some(format!("Invalid filter: {}", e));
+                }
+            }
+        }
+    }
+
+    /// Filters the frames shown on the typed in expression, or shows every
+    /// frame if no expression is typed in. A bad expression leaves the
+    /// current filter in place.
+    pub fn set_frame_filter(&mut self, filter_input: &str) -> Result<(), String> {
+        self.frame_filter = match filter_input.trim() {
+            "" => None,
+            filter_input => Some(FrameFilter::parse(filter_input)?),
         };
 
-        self.table_state.select(Some(i));
+        Ok(())
     }
 
-    pub fn select_latest_msg(&mut self) {
-        self.table_state.select(Some(0));
+    /// Captures the typed in CAN interfaces instead of the current frame
+    /// sources. All interfaces are opened before switching, so that a bad
+    /// interface name leaves the current capture running.
+    pub fn switch_interfaces(&mut self, interface_input: &str) {
+        let can_interfaces = interface_input.split_whitespace().collect::<Vec<&str>>();
+        if can_interfaces.is_empty() {
+            return;
+        }
+
+        let frame_sources = can_interfaces
+            .iter()
+            .map(|can_interface| {
+                SocketCanSource::open(can_interface, &self.socket_filters)
+                    .map(|frame_source| Box::new(frame_source) as Box<dyn FrameSource>)
+                    .map_err(|e| format!("Failed to open {}: {}", can_interface, e))
+            })
+            .collect::<std::result::Result<Vec<Box<dyn FrameSource>>, String>>();
+
+        match frame_sources {
+            Ok(frame_sources) => self.frame_captor.switch_frame_sources(frame_sources),
+            Err(e) => self.status_message = Some(e),
+        }
     }
 
     pub fn toggle_frame_table_ui(&mut self) {
+        self.table_state = TableState::default();
+
         match self.frame_view {
             FrameView::FrameList => {
                 self.frame_view = FrameView::FrameSet;
@@ -143,6 +854,31 @@ impl<'a> App<'a> {
     }
 }
 
+/// Where to go to in the frame list
+#[derive(Debug, PartialEq)]
+enum GoToTarget {
+    FrameNumber(u64),
+    /// Seconds since the unix epoch
+    Timestamp(f64),
+}
+
+/// Parses a frame number, or a timestamp in seconds, which is told apart from
+/// a frame number by a decimal point or an `s` suffix, such as `1700000000s`
+fn parse_go_to_target(target: &str) -> std::result::Result<GoToTarget, String> {
+    let target = target.trim();
+
+    match target.strip_suffix('s') {
+        Some(seconds) => seconds.trim_end().parse().map(GoToTarget::Timestamp).ok(),
+        None if target.contains('.') => target.parse().map(GoToTarget::Timestamp).ok(),
+        None => target.parse().map(GoToTarget::FrameNumber).ok(),
+    }
+    .filter(|target| match target {
+        GoToTarget::FrameNumber(_) => true,
+        GoToTarget::Timestamp(timestamp) => timestamp.is_finite(),
+    })
+    .ok_or_else(|| format!("Not a frame number or timestamp: {}", target))
+}
+
 fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     mut app: App,
@@ -151,21 +887,48 @@ fn run_app<B: Backend>(
     let mut last_tick = Instant::now();
 
     loop {
+        app.frame_captor.update();
         terminal.draw(|f| ui(f, &mut app))?;
 
         let timeout = tick_rate.saturating_sub(last_tick.elapsed());
         if crossterm::event::poll(timeout)? {
             if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
+                if key.kind == KeyEventKind::Press && app.text_input.is_some() {
+                    handle_text_input_key(&mut app, key.code);
+                } else if key.kind == KeyEventKind::Press {
                     match key.code {
                         KeyCode::Char('q') => return Ok(()),
                         KeyCode::Char('c') => {
                             app.frame_captor.clear_captured_frames();
+                            if let Some(frozen_display) = &mut app.frozen_display {
+                                frozen_display.frame_set.clear();
+                            }
                         }
                         KeyCode::Char('j') | KeyCode::Down => app.select_next_msg(),
                         KeyCode::Char('k') | KeyCode::Up => app.select_prev_msg(),
-                        KeyCode::Char('t') => app.select_latest_msg(),
+                        KeyCode::Char('t') | KeyCode::Home => app.select_latest_msg(),
+                        KeyCode::End => app.scroll_to_oldest(),
+                        KeyCode::PageDown => app.page_down(),
+                        KeyCode::PageUp => app.page_up(),
+                        KeyCode::Char('g') => app.start_text_input(InputPrompt::GoToFrame),
+                        KeyCode::Char('/') => app.start_text_input(InputPrompt::Filter),
                         KeyCode::Char('v') => app.toggle_frame_table_ui(),
+                        KeyCode::Char('b') => app.show_bit_heatmap = !app.show_bit_heatmap,
+                        KeyCode::Char('[') => app.scroll_bit_heatmap(-1),
+                        KeyCode::Char(']') => app.scroll_bit_heatmap(1),
+                        KeyCode::Char('d') => app.show_frame_inspector = !app.show_frame_inspector,
+                        KeyCode::Char('<') => app.scroll_frame_inspector(-1),
+                        KeyCode::Char('>') => app.scroll_frame_inspector(1),
+                        KeyCode::Char('m') => {
+                            if let FrameView::FrameList = app.frame_view {
+                                app.toggle_marked_frame();
+                            }
+                        }
+                        KeyCode::Char('r') => app.record_baseline(),
+                        KeyCode::Char('o') => app.toggle_changes_only(),
+                        KeyCode::Char('p') => app.toggle_frozen_display(),
+                        KeyCode::Char('s') => app.toggle_capture(),
+                        KeyCode::Char('i') => app.start_text_input(InputPrompt::SwitchInterfaces),
                         _ => {}
                     }
                 }
@@ -178,6 +941,24 @@ fn run_app<B: Backend>(
     }
 }
 
+/// Keys typed while the input prompt is open edit its text, rather than
+/// acting as key bindings
+fn handle_text_input_key(app: &mut App, key_code: KeyCode) {
+    let Some(text_input) = &mut app.text_input else {
+        return;
+    };
+
+    match key_code {
+        KeyCode::Enter => app.submit_text_input(),
+        KeyCode::Esc => app.text_input = None,
+        KeyCode::Backspace => {
+            text_input.text.pop();
+        }
+        KeyCode::Char(c) => text_input.text.push(c),
+        _ => {}
+    }
+}
+
 fn parse_filter_ids(raw_ids: Vec<String>) -> Vec<embedded_can::Id> {
     let mut filter_ids: Vec<embedded_can::Id> = Vec::new();
 
@@ -208,13 +989,113 @@ fn parse_filter_ids(raw_ids: Vec<String>) -> Vec<embedded_can::Id> {
     filter_ids
 }
 
+fn parse_hex_value(raw: &str) -> std::result::Result<u32, String> {
+    raw.strip_prefix("0x")
+        .and_then(|raw| u32::from_str_radix(raw, 16).ok())
+        .ok_or_else(|| format!("'{}' is not a hexadecimal value such as 0x123", raw))
+}
+
+fn parse_filter_id_mask(raw_id_mask: &str) -> std::result::Result<(u32, u32), String> {
+    let (raw_id, raw_mask) = raw_id_mask.split_once(':').ok_or_else(|| {
+        format!(
+            "Expected <ID>:<MASK>, such as 0x100:0x700, found '{}'",
+            raw_id_mask
+        )
+    })?;
+
+    Ok((parse_hex_value(raw_id)?, parse_hex_value(raw_mask)?))
+}
+
+fn parse_message_period(raw_message_period: &str) -> std::result::Result<(u32, Duration), String> {
+    let (raw_id, raw_period) = raw_message_period.split_once(':').ok_or_else(|| {
+        format!(
+            "Expected <ID>:<MILLISECONDS>, such as 0x100:20, found '{}'",
+            raw_message_period
+        )
+    })?;
+    let period = raw_period
+        .parse::<u64>()
+        .map_err(|_| format!("'{}' is not a number of milliseconds", raw_period))?;
+
+    Ok((parse_hex_value(raw_id)?, Duration::from_millis(period)))
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    let mut terminal = ratatui::init();
-    terminal.clear()?;
+    let filter_ids = args.filter_frame_ids.map(parse_filter_ids);
+
+    let socket_filters = if args.kernel_filter {
+        frame_filter::get_socket_filters(
+            filter_ids.as_deref().unwrap_or_default(),
+            &args.filter_id_masks.unwrap_or_default(),
+        )
+    } else {
+        vec![]
+    };
 
-    let frame_captor = FrameCaptor::new(args.can_interface.clone())?;
+    // Open every socket before starting any capture, so that a bad
+    // interface name fails without leaving other captures running
+    let mut frame_sources = args
+        .can_interface
+        .iter()
+        .map(|can_interface| {
+            SocketCanSource::open(can_interface, &socket_filters)
+                .map(|frame_source| Box::new(frame_source) as Box<dyn FrameSource>)
+                .with_context(|| format!("Failed to open {}", can_interface))
+        })
+        .collect::<Result<Vec<Box<dyn FrameSource>>>>()?;
+
+    if let Some(serial_device) = &args.serial {
+        frame_sources.push(Box::new(
+            SlcanSource::open(serial_device, args.bitrate, args.slcan_timestamps)
+                .with_context(|| format!("Failed to open {}", serial_device))?,
+        ));
+    }
+
+    if let Some(log_file) = &args.replay {
+        frame_sources.push(Box::new(
+            ReplaySource::open(log_file, args.replay_speed)
+                .with_context(|| format!("Failed to open {}", log_file))?,
+        ));
+    }
+
+    let max_frames = [
+        args.history_max_frames,
+        args.history_max_megabytes
+            .map(FrameHistoryLimit::max_frames_for_megabytes),
+    ]
+    .into_iter()
+    .flatten()
+    .min();
+
+    let captured_frame_state = match max_frames {
+        Some(max_frames) => CapturedFrameState::with_history_limit(FrameHistoryLimit {
+            max_frames,
+            spill_file: args
+                .history_spill_file
+                .as_ref()
+                .map(|spill_file| {
+                    std::fs::File::create(spill_file)
+                        .with_context(|| format!("Failed to create {}", spill_file))
+                })
+                .transpose()?,
+        }),
+        None => CapturedFrameState::new(),
+    };
+
+    let captured_frame_state = match args.timeout_period_multiple > 0.0 {
+        true => captured_frame_state.with_message_timeouts(MessageTimeouts {
+            period_multiple: args.timeout_period_multiple,
+            configured_periods: args
+                .message_periods
+                .map(HashMap::from_iter)
+                .unwrap_or_default(),
+        }),
+        false => captured_frame_state,
+    };
+
+    let frame_captor = FrameCaptor::new(frame_sources, captured_frame_state);
 
     let mut app = App::new(
         APP_TITLE,
@@ -223,13 +1104,24 @@ fn main() -> Result<()> {
         false,
         frame_captor,
     );
+    app.socket_filters = socket_filters;
+    app.change_highlight_duration = Duration::from_millis(args.change_highlight_ms);
 
-    if let Some(filter_ids) = args.filter_frame_ids {
-        app.frame_id_filter = Some(FrameIdFilter {
-            ids: parse_filter_ids(filter_ids),
-            filter_callback: frame_filter::filter_frame_on_ids,
-        })
-    }
+    // Frames filtered out on the sockets never reach the frame list, so
+    // there is no need to filter them again when drawing
+    let id_filter = filter_ids
+        .filter(|_| !args.kernel_filter)
+        .map(|filter_ids| FrameFilter::from_ids(&filter_ids));
+
+    app.frame_filter = match (id_filter, args.filter) {
+        (Some(id_filter), Some(expression_filter)) => Some(id_filter.and(expression_filter)),
+        (id_filter, expression_filter) => id_filter.or(expression_filter),
+    };
+
+    // Everything which can fail is opened before the terminal is taken over,
+    // so that errors are reported on a terminal which is left as it was
+    let mut terminal = ratatui::init();
+    terminal.clear()?;
 
     match run_app(
         &mut terminal,
@@ -244,3 +1136,62 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_go_to_targets() {
+        assert_eq!(parse_go_to_target("42"), Ok(GoToTarget::FrameNumber(42)));
+        assert_eq!(
+            parse_go_to_target(" 1700000000.25 "),
+            Ok(GoToTarget::Timestamp(1_700_000_000.25))
+        );
+        assert_eq!(
+            parse_go_to_target("1700000000s"),
+            Ok(GoToTarget::Timestamp(1_700_000_000.0))
+        );
+        assert_eq!(parse_go_to_target("12 s"), Ok(GoToTarget::Timestamp(12.0)));
+
+        for target in ["", "-1", "s", "abc", "1.2.3", "infs", "nan."] {
+            assert!(parse_go_to_target(target).is_err(), "{:?}", target);
+        }
+    }
+
+    #[test]
+    fn parses_filter_id_masks() {
+        assert_eq!(parse_filter_id_mask("0x100:0x700"), Ok((0x100, 0x700)));
+        assert_eq!(
+            parse_filter_id_mask("0x18FF0000:0x1FFF0000"),
+            Ok((0x18FF_0000, 0x1FFF_0000))
+        );
+
+        for id_mask in [
+            "",
+            "0x100",
+            "100:700",
+            "0x100:",
+            "0xZZ:0x700",
+            "0x100:0x700:0x1",
+        ] {
+            assert!(parse_filter_id_mask(id_mask).is_err(), "{:?}", id_mask);
+        }
+    }
+
+    #[test]
+    fn parses_message_periods() {
+        assert_eq!(
+            parse_message_period("0x100:20"),
+            Ok((0x100, Duration::from_millis(20)))
+        );
+
+        for message_period in ["", "0x100", "12:3", "0x100:", "0x100:-5", "0x100:1.5"] {
+            assert!(
+                parse_message_period(message_period).is_err(),
+                "{:?}",
+                message_period
+            );
+        }
+    }
+}
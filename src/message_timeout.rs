@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::frame::{get_raw_id, CountedFrame, FrameSetKey, FrameType};
+
+/// Number of periods which must have been observed for an ID before it can
+/// time out, so that a single short gap is not taken as its period
+const MIN_OBSERVED_PERIODS: u64 = 2;
+
+/// When a message is considered to have stopped
+pub struct MessageTimeouts {
+    /// An ID times out once this many of its periods passed without a frame
+    pub period_multiple: f64,
+    /// Periods of IDs, which take precedence over the periods observed on
+    /// the bus
+    pub configured_periods: HashMap<u32, Duration>,
+}
+
+impl MessageTimeouts {
+    /// Time after the latest frame of an ID at which it times out, if its
+    /// period is known
+    fn get_timeout(&self, id: u32, counted_frame: &CountedFrame) -> Option<Duration> {
+        let period = match self.configured_periods.get(&id) {
+            Some(period) => *period,
+            None => {
+                let period_statistics = &counted_frame.period_statistics;
+                if period_statistics.get_count() < MIN_OBSERVED_PERIODS {
+                    return None;
+                }
+                Duration::from_secs_f64(period_statistics.get_current_period()?)
+            }
+        };
+
+        Some(period.mul_f64(self.period_multiple))
+    }
+}
+
+/// An ID which stopped arriving for longer than its timeout
+pub struct TimeoutEvent {
+    pub key: FrameSetKey,
+    /// Timestamp of the latest frame before the ID went missing
+    pub start: Duration,
+    /// Timestamp of the frame which ended the timeout, or `None` while the ID
+    /// is still missing
+    pub end: Option<Duration>,
+    pub timeout: Duration,
+}
+
+/// Detects IDs which stopped arriving, keeping a log of the timeouts
+pub struct MessageTimeoutDetector {
+    message_timeouts: MessageTimeouts,
+    timeout_events: Vec<TimeoutEvent>,
+    /// Timestamp of the newest frame, and when it was received. Frame
+    /// timestamps are not always wall-clock times, as replayed frames keep
+    /// their original timestamps, so time is measured from the newest frame.
+    newest_frame: Option<(Duration, Instant)>,
+    /// Capture time at which a stopped capture was last started again. IDs
+    /// last seen before then only time out once their timeout has passed
+    /// since, as nothing was captured in between.
+    resume_time: Option<Duration>,
+    /// When a stopped capture was started again, until the first frame
+    /// after it has been received
+    resumed_at: Option<Instant>,
+}
+
+impl MessageTimeoutDetector {
+    pub fn new(message_timeouts: MessageTimeouts) -> Self {
+        Self {
+            message_timeouts,
+            timeout_events: vec![],
+            newest_frame: None,
+            resume_time: None,
+            resumed_at: None,
+        }
+    }
+
+    pub fn get_timeout_events(&self) -> &[TimeoutEvent] {
+        &self.timeout_events
+    }
+
+    pub fn clear(&mut self) {
+        self.timeout_events.clear();
+    }
+
+    /// Current time in terms of the frame timestamps
+    fn get_capture_time(&self) -> Option<Duration> {
+        self.newest_frame
+            .map(|(timestamp, received)| timestamp + received.elapsed())
+    }
+
+    /// Picks the capture time up where it was when capture was stopped, so
+    /// that the time capture was stopped for does not count against any ID
+    pub fn resume(&mut self) {
+        if let Some((newest_timestamp, _)) = self.newest_frame {
+            let now = Instant::now();
+            self.newest_frame = Some((newest_timestamp, now));
+            self.resume_time = Some(newest_timestamp);
+            self.resumed_at = Some(now);
+        }
+    }
+
+    /// Ends the timeout of the frame's ID, if it had timed out. Called once
+    /// the frame has been counted in the frame set.
+    pub fn frame_received(
+        &mut self,
+        key: &FrameSetKey,
+        counted_frame: &mut CountedFrame,
+        frame_type: FrameType,
+        timestamp: Duration,
+    ) {
+        // Live frames are stamped with the wall-clock time, which moved on
+        // while capture was stopped, unlike replayed ones, so the resume is
+        // placed in terms of the first frame received after it
+        if let Some(resumed_at) = self.resumed_at.take() {
+            self.resume_time = Some(timestamp.saturating_sub(resumed_at.elapsed()));
+        }
+
+        if self
+            .newest_frame
+            .is_none_or(|(newest_timestamp, _)| timestamp > newest_timestamp)
+        {
+            self.newest_frame = Some((timestamp, Instant::now()));
+        }
+
+        // Remote requests do not carry the message, so they do not end a timeout
+        if frame_type != FrameType::Data || counted_frame.missing_since.take().is_none() {
+            return;
+        }
+
+        if let Some(timeout_event) = self
+            .timeout_events
+            .iter_mut()
+            .rev()
+            .find(|timeout_event| timeout_event.key == *key && timeout_event.end.is_none())
+        {
+            timeout_event.end = Some(timestamp);
+        }
+    }
+
+    /// Marks the IDs which have not been received within their timeout as
+    /// missing, logging a timeout event for each
+    pub fn check_timeouts<'a>(
+        &mut self,
+        frame_set: impl Iterator<Item = (&'a FrameSetKey, &'a mut CountedFrame)>,
+    ) {
+        let Some(capture_time) = self.get_capture_time() else {
+            return;
+        };
+
+        for (key, counted_frame) in frame_set {
+            if counted_frame.missing_since.is_some() {
+                continue;
+            }
+
+            let Some(last_seen) = counted_frame.last_seen else {
+                continue;
+            };
+            let Some(timeout) = self
+                .message_timeouts
+                .get_timeout(get_raw_id(key.1), counted_frame)
+            else {
+                continue;
+            };
+
+            let missing_from = last_seen.max(self.resume_time.unwrap_or_default());
+            if capture_time.saturating_sub(missing_from) > timeout {
+                counted_frame.missing_since = Some(last_seen);
+                self.timeout_events.push(TimeoutEvent {
+                    key: key.clone(),
+                    start: last_seen,
+                    end: None,
+                    timeout,
+                });
+            }
+        }
+    }
+}
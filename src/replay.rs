@@ -0,0 +1,262 @@
+use socketcan::dump::{self, CanDumpRecord, ParseError};
+use socketcan::CanTimestamps;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::frame_source::{FrameSource, ReceivedFrame, RECEIVE_TIMEOUT};
+
+/// Longest log line passed on at once, matching the cap of `dump::Reader`
+const MAX_LOG_LINE_LENGTH: u64 = 64 * 1024;
+
+/// Lines of a log file, without blank lines and `#` comment lines. candump
+/// does not write these, but the spill file of a history limit notes dropped
+/// frames in comment lines, which `dump::Reader` would reject.
+struct LogLines<R> {
+    log_reader: R,
+    line: Vec<u8>,
+    line_offset: usize,
+}
+
+impl<R: BufRead> Read for LogLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.line_offset == self.line.len() {
+            self.line.clear();
+            self.line_offset = 0;
+            if (&mut self.log_reader)
+                .take(MAX_LOG_LINE_LENGTH)
+                .read_until(b'\n', &mut self.line)?
+                == 0
+            {
+                return Ok(0);
+            }
+
+            if self
+                .line
+                .trim_ascii_start()
+                .first()
+                .is_none_or(|&first| first == b'#')
+            {
+                self.line.clear();
+            }
+        }
+
+        let read_len = (&self.line[self.line_offset..]).read(buf)?;
+        self.line_offset += read_len;
+        Ok(read_len)
+    }
+}
+
+/// Frames replayed from a log file recorded with `candump -l`. Frames keep
+/// the timestamps and interface names they were recorded with.
+pub struct ReplaySource {
+    log_reader: dump::Reader<BufReader<LogLines<BufReader<File>>>>,
+    /// The next frame, read from the log but not yet due
+    pending_record: Option<CanDumpRecord>,
+    /// Replay speed relative to the original timing, or `None` to replay as
+    /// fast as possible
+    speed: Option<f64>,
+    /// When the replay started, and the log timestamp of the first frame, in
+    /// microseconds
+    replay_start: Option<(Instant, u64)>,
+    interfaces: HashMap<String, Arc<str>>,
+    log_file: String,
+}
+
+impl ReplaySource {
+    /// Opens the log file for replay. A speed of 0 or less replays the log as
+    /// fast as possible.
+    pub fn open(log_file: &str, speed: f64) -> io::Result<Self> {
+        Ok(Self {
+            log_reader: dump::Reader::from_reader(LogLines {
+                log_reader: BufReader::new(File::open(log_file)?),
+                line: Vec::new(),
+                line_offset: 0,
+            }),
+            pending_record: None,
+            speed: (speed > 0.0).then_some(speed),
+            replay_start: None,
+            interfaces: HashMap::new(),
+            log_file: log_file.to_string(),
+        })
+    }
+
+    /// Sleeps until the frame recorded at the given log timestamp is due,
+    /// keeping the original inter-frame timing scaled by the replay speed.
+    /// Returns whether the frame is due, as the sleep is cut short after
+    /// `RECEIVE_TIMEOUT`.
+    fn wait_for_frame(&mut self, t_us: u64) -> bool {
+        let Some(speed) = self.speed else {
+            return true;
+        };
+
+        let (replay_start, first_t_us) = *self.replay_start.get_or_insert((Instant::now(), t_us));
+        let due =
+            replay_start + Duration::from_micros(t_us.saturating_sub(first_t_us)).div_f64(speed);
+        let until_due = due.saturating_duration_since(Instant::now());
+
+        std::thread::sleep(until_due.min(RECEIVE_TIMEOUT));
+        until_due <= RECEIVE_TIMEOUT
+    }
+}
+
+impl FrameSource for ReplaySource {
+    /// The interfaces of a log are only known as its frames are replayed
+    fn interfaces(&self) -> Vec<Arc<str>> {
+        vec![]
+    }
+
+    fn name(&self) -> String {
+        self.log_file.clone()
+    }
+
+    /// Resumes the replay with the frame it stopped at, which is replayed
+    /// right away, keeping the original timing from there on
+    fn reopen(&mut self) -> io::Result<()> {
+        self.replay_start = None;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<ReceivedFrame> {
+        let record = match self.pending_record.take() {
+            Some(record) => record,
+            None => match self.log_reader.next_record() {
+                Ok(Some(record)) => record,
+                Ok(None) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Err(ParseError::Io(e)) => return Err(e),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            },
+        };
+
+        if !self.wait_for_frame(record.t_us) {
+            self.pending_record = Some(record);
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        let interface = self
+            .interfaces
+            .entry(record.device)
+            .or_insert_with_key(|device| device.as_str().into());
+
+        Ok(ReceivedFrame {
+            frame: record.frame,
+            interface: Arc::clone(interface),
+            timestamps: CanTimestamps {
+                socket: Some(UNIX_EPOCH + Duration::from_micros(record.t_us)),
+                ..Default::default()
+            },
+            dropped_frames: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_can::Frame;
+
+    /// Log file with the given candump lines, removed once the test is done
+    struct TestLog(std::path::PathBuf);
+
+    impl TestLog {
+        fn new(name: &str, lines: &[&str]) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "can-viewer-tui-{}-{}.log",
+                name,
+                std::process::id()
+            ));
+            std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+            Self(path)
+        }
+
+        fn open(&self, speed: f64) -> ReplaySource {
+            ReplaySource::open(self.0.to_str().unwrap(), speed).unwrap()
+        }
+    }
+
+    impl Drop for TestLog {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn replays_recorded_frames() {
+        let log = TestLog::new(
+            "replays",
+            &["(1.000000) can0 123#0102", "(1.500000) can1 12345678#03"],
+        );
+        let mut replay_source = log.open(0.0);
+        // Errors are reported with the log file, as no interface is known
+        // before the frames are read
+        assert_eq!(replay_source.name(), log.0.to_str().unwrap());
+
+        let received_frame = replay_source.receive().unwrap();
+        assert_eq!(&*received_frame.interface, "can0");
+        assert_eq!(received_frame.frame.data(), [1, 2]);
+        assert_eq!(
+            received_frame.timestamps.socket,
+            Some(UNIX_EPOCH + Duration::from_secs(1))
+        );
+
+        let received_frame = replay_source.receive().unwrap();
+        assert_eq!(&*received_frame.interface, "can1");
+        assert!(received_frame.frame.is_extended());
+
+        let error = replay_source.receive().err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn resumes_where_it_stopped() {
+        let log = TestLog::new(
+            "resumes",
+            &[
+                "(1.000000) can0 001#01",
+                "(1.200000) can0 002#02",
+                "(1.400000) can0 003#03",
+            ],
+        );
+        let mut replay_source = log.open(1.0);
+
+        let received_frame = replay_source.receive().unwrap();
+        assert_eq!(received_frame.frame.data(), [1]);
+
+        // The frames after a pause keep their timing, rather than all of the
+        // frames which would have been due during the pause arriving at once
+        std::thread::sleep(Duration::from_millis(500));
+        replay_source.reopen().unwrap();
+
+        let received_frame = replay_source.receive().unwrap();
+        assert_eq!(received_frame.frame.data(), [2]);
+
+        let error = replay_source.receive().err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn skips_comment_and_blank_lines() {
+        let log = TestLog::new(
+            "comments",
+            &[
+                "# 3 frames dropped on can0",
+                "(1.000000) can0 123#01",
+                "",
+                "  # indented comment",
+                "(1.100000) can0 123#02",
+                "not a frame",
+            ],
+        );
+        let mut replay_source = log.open(0.0);
+
+        assert_eq!(replay_source.receive().unwrap().frame.data(), [1]);
+        assert_eq!(replay_source.receive().unwrap().frame.data(), [2]);
+
+        // Lines which are neither comments nor frames are still errors
+        let error = replay_source.receive().err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
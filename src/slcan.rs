@@ -0,0 +1,516 @@
+use embedded_can::{ExtendedId, Frame, Id, StandardId};
+use nix::fcntl::OFlag;
+use nix::poll::{self, PollFd, PollFlags};
+use nix::sys::termios::{self, BaudRate, FlushArg, SetArg};
+use socketcan::{CanAnyFrame, CanFrame, CanTimestamps};
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::AsFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::frame_source::{FrameSource, ReceivedFrame, RECEIVE_TIMEOUT};
+
+/// Baud rate of the serial line to the adapter. USB adapters ignore it, but
+/// adapters behind a real UART expect the Lawicel default.
+const SLCAN_SERIAL_BAUD_RATE: BaudRate = BaudRate::B115200;
+/// The adapter's timestamps are milliseconds which wrap around every minute
+const SLCAN_TIMESTAMP_WRAP_MILLISECONDS: u64 = 60_000;
+/// Longest line the adapter sends is an extended data frame with 8 bytes of
+/// payload and a timestamp, so anything much longer is garbage
+const SLCAN_MAX_LINE_LENGTH: usize = 64;
+
+/// Frames received from a USB-serial CAN adapter speaking the slcan (Lawicel)
+/// ASCII protocol
+pub struct SlcanSource {
+    interface: Arc<str>,
+    bitrate: u32,
+    serial_reader: BufReader<File>,
+    serial_writer: File,
+    /// Start of a line which was cut off by the receive timeout
+    partial_line: Vec<u8>,
+    adapter_clock: Option<AdapterClock>,
+    /// Whether the channel is closed when the source is dropped, which a
+    /// session replaced by a reopened one must not do
+    close_channel_on_drop: bool,
+}
+
+/// Turns the adapter's wrapping millisecond timestamps into wall-clock time,
+/// anchored at the time the first timestamped frame was received
+struct AdapterClock {
+    origin: Option<SystemTime>,
+    last_timestamp_ms: u64,
+    elapsed_ms: u64,
+}
+
+impl AdapterClock {
+    fn new() -> Self {
+        Self {
+            origin: None,
+            last_timestamp_ms: 0,
+            elapsed_ms: 0,
+        }
+    }
+
+    fn get_timestamps(&mut self, timestamp_ms: u16) -> CanTimestamps {
+        let timestamp_ms = timestamp_ms as u64;
+
+        let origin = match self.origin {
+            Some(origin) => {
+                // Gaps longer than a minute can not be told apart from
+                // shorter ones, as the timestamp has wrapped around
+                self.elapsed_ms += (timestamp_ms + SLCAN_TIMESTAMP_WRAP_MILLISECONDS
+                    - self.last_timestamp_ms)
+                    % SLCAN_TIMESTAMP_WRAP_MILLISECONDS;
+                origin
+            }
+            None => *self.origin.insert(SystemTime::now()),
+        };
+        self.last_timestamp_ms = timestamp_ms;
+
+        CanTimestamps {
+            socket: Some(origin + Duration::from_millis(self.elapsed_ms)),
+            sw: None,
+            hw: Some(Duration::from_millis(timestamp_ms)),
+        }
+    }
+}
+
+impl SlcanSource {
+    /// Opens the adapter on the given serial device, sets it up for the given
+    /// CAN bitrate and opens the CAN channel. If adapter timestamps are enabled
+    /// frames are stamped with the adapter's clock rather than on arrival.
+    pub fn open(serial_device: &str, bitrate: u32, adapter_timestamps: bool) -> io::Result<Self> {
+        let bitrate_command = get_bitrate_command(bitrate).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Bitrate {} is not supported by slcan adapters", bitrate),
+            )
+        })?;
+
+        let serial_port = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(OFlag::O_NOCTTY.bits())
+            .open(serial_device)?;
+
+        let mut serial_settings = termios::tcgetattr(&serial_port)?;
+        termios::cfmakeraw(&mut serial_settings);
+        termios::cfsetspeed(&mut serial_settings, SLCAN_SERIAL_BAUD_RATE)?;
+        termios::tcsetattr(&serial_port, SetArg::TCSANOW, &serial_settings)?;
+        termios::tcflush(&serial_port, FlushArg::TCIOFLUSH)?;
+
+        let mut serial_writer = serial_port.try_clone()?;
+
+        // Close the channel first, as the adapter refuses to change its
+        // settings while a previous session left the channel open
+        serial_writer.write_all(b"C\r")?;
+        serial_writer.write_all(bitrate_command.as_bytes())?;
+        match adapter_timestamps {
+            true => serial_writer.write_all(b"Z1\r")?,
+            false => serial_writer.write_all(b"Z0\r")?,
+        }
+        serial_writer.write_all(b"O\r")?;
+
+        Ok(Self {
+            interface: serial_device.into(),
+            bitrate,
+            serial_reader: BufReader::new(serial_port),
+            serial_writer,
+            partial_line: vec![],
+            adapter_clock: adapter_timestamps.then(AdapterClock::new),
+            close_channel_on_drop: true,
+        })
+    }
+}
+
+impl SlcanSource {
+    /// Reads the next line from the adapter, waiting until the deadline at
+    /// most. The serial port is only read once it has data, so a line which
+    /// is cut off or never ends can not block the capture thread; the start
+    /// of a cut off line is kept for the next call to complete.
+    fn read_line(&mut self, deadline: Instant) -> io::Result<Vec<u8>> {
+        loop {
+            if self.serial_reader.buffer().is_empty() {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                let mut poll_fds = [PollFd::new(
+                    self.serial_reader.get_ref().as_fd(),
+                    PollFlags::POLLIN,
+                )];
+                if poll::poll(&mut poll_fds, timeout.as_millis() as u16)? == 0 {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+            }
+
+            // A serial port only reads nothing once it was hung up, such as
+            // when its adapter is unplugged, so it is lost rather than at its end
+            let available = self.serial_reader.fill_buf()?;
+            if available.is_empty() {
+                return Err(io::Error::from_raw_os_error(nix::libc::EIO));
+            }
+
+            match available.iter().position(|c| *c == b'\r') {
+                Some(line_end) => {
+                    self.partial_line.extend_from_slice(&available[..=line_end]);
+                    self.serial_reader.consume(line_end + 1);
+                    return Ok(std::mem::take(&mut self.partial_line));
+                }
+                None => {
+                    let available_len = available.len();
+                    self.partial_line.extend_from_slice(available);
+                    self.serial_reader.consume(available_len);
+
+                    if self.partial_line.len() > SLCAN_MAX_LINE_LENGTH {
+                        return Err(invalid_line(&std::mem::take(&mut self.partial_line)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for SlcanSource {
+    fn drop(&mut self) {
+        // Leave the adapter with its channel closed, the way it was found
+        if self.close_channel_on_drop {
+            let _ = self.serial_writer.write_all(b"C\r");
+        }
+    }
+}
+
+impl FrameSource for SlcanSource {
+    fn interfaces(&self) -> Vec<Arc<str>> {
+        vec![Arc::clone(&self.interface)]
+    }
+
+    /// Closes the channel and sets the adapter up again on a newly opened
+    /// port, as when it was first opened, which also picks the adapter up
+    /// again after it was replugged. The old session is dropped without
+    /// closing the channel, which the new session has just opened.
+    fn reopen(&mut self) -> io::Result<()> {
+        let reopened = Self::open(&self.interface, self.bitrate, self.adapter_clock.is_some())?;
+        let mut replaced = std::mem::replace(self, reopened);
+        replaced.close_channel_on_drop = false;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<ReceivedFrame> {
+        let deadline = Instant::now() + RECEIVE_TIMEOUT;
+
+        loop {
+            let line = self.read_line(deadline)?;
+
+            if let Some((frame, timestamp_ms)) = parse_frame(&line)? {
+                let timestamps = match (&mut self.adapter_clock, timestamp_ms) {
+                    (Some(adapter_clock), Some(timestamp_ms)) => {
+                        adapter_clock.get_timestamps(timestamp_ms)
+                    }
+                    _ => CanTimestamps {
+                        socket: Some(SystemTime::now()),
+                        ..Default::default()
+                    },
+                };
+
+                return Ok(ReceivedFrame {
+                    frame,
+                    interface: Arc::clone(&self.interface),
+                    timestamps,
+                    dropped_frames: 0,
+                });
+            }
+        }
+    }
+}
+
+/// Command setting up the adapter for one of the standard CAN bitrates
+fn get_bitrate_command(bitrate: u32) -> Option<&'static str> {
+    match bitrate {
+        10_000 => Some("S0\r"),
+        20_000 => Some("S1\r"),
+        50_000 => Some("S2\r"),
+        100_000 => Some("S3\r"),
+        125_000 => Some("S4\r"),
+        250_000 => Some("S5\r"),
+        500_000 => Some("S6\r"),
+        800_000 => Some("S7\r"),
+        1_000_000 => Some("S8\r"),
+        _ => None,
+    }
+}
+
+fn invalid_line(line: &[u8]) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "Invalid slcan line: {:?}",
+            String::from_utf8_lossy(line).trim_end()
+        ),
+    )
+}
+
+fn parse_hex(line: &[u8], digits: &[u8]) -> io::Result<u32> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+        .ok_or_else(|| invalid_line(line))
+}
+
+/// Parses a line received from the adapter, such as `t1232aabb` or
+/// `T123456782aabb` for data frames and `r1232` or `R123456782` for remote
+/// frames, optionally followed by a 4 digit hex millisecond timestamp.
+/// Responses to commands carry no frame and are skipped.
+fn parse_frame(line: &[u8]) -> io::Result<Option<(CanAnyFrame, Option<u16>)>> {
+    // A bell is the adapter's response to a failed command, and may be
+    // directly followed by the next frame
+    let frame_line = line
+        .iter()
+        .position(|c| *c != b'\x07')
+        .map_or(&[][..], |start| &line[start..]);
+    let frame_line = frame_line.strip_suffix(b"\r").unwrap_or(frame_line);
+
+    let (id_digits, is_remote) = match frame_line.first() {
+        None | Some(b'z') | Some(b'Z') => return Ok(None),
+        Some(b't') => (3, false),
+        Some(b'T') => (8, false),
+        Some(b'r') => (3, true),
+        Some(b'R') => (8, true),
+        Some(_) => return Err(invalid_line(line)),
+    };
+
+    let dlc_index = 1 + id_digits;
+    if frame_line.len() <= dlc_index {
+        return Err(invalid_line(line));
+    }
+
+    let raw_id = parse_hex(line, &frame_line[1..dlc_index])?;
+    let id: Id = match id_digits {
+        3 => StandardId::new(raw_id as u16).map(Id::Standard),
+        _ => ExtendedId::new(raw_id).map(Id::Extended),
+    }
+    .ok_or_else(|| invalid_line(line))?;
+
+    let dlc = parse_hex(line, &frame_line[dlc_index..dlc_index + 1])? as usize;
+    let data_digits = if is_remote { 0 } else { 2 * dlc };
+    let data_end = dlc_index + 1 + data_digits;
+    if dlc > 8 || frame_line.len() < data_end {
+        return Err(invalid_line(line));
+    }
+
+    let data = frame_line[dlc_index + 1..data_end]
+        .chunks(2)
+        .map(|byte| parse_hex(line, byte).map(|byte| byte as u8))
+        .collect::<io::Result<Vec<u8>>>()?;
+
+    let timestamp_ms = match &frame_line[data_end..] {
+        [] => None,
+        // The adapter counts milliseconds within a minute, so anything past that is garbage
+        timestamp @ [_, _, _, _] => match parse_hex(line, timestamp)? {
+            timestamp_ms if u64::from(timestamp_ms) < SLCAN_TIMESTAMP_WRAP_MILLISECONDS => {
+                Some(timestamp_ms as u16)
+            }
+            _ => return Err(invalid_line(line)),
+        },
+        _ => return Err(invalid_line(line)),
+    };
+
+    let frame = match is_remote {
+        true => CanFrame::new_remote(id, dlc),
+        false => CanFrame::new(id, &data),
+    }
+    .ok_or_else(|| invalid_line(line))?;
+
+    Ok(Some((frame.into(), timestamp_ms)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::fd::OwnedFd;
+
+    fn parse(line: &str) -> io::Result<Option<(CanAnyFrame, Option<u16>)>> {
+        parse_frame(line.as_bytes())
+    }
+
+    #[test]
+    fn parses_data_frames() {
+        let (frame, timestamp_ms) = parse("t1232aabb\r").unwrap().unwrap();
+        assert_eq!(frame.id(), Id::Standard(StandardId::new(0x123).unwrap()));
+        assert_eq!(frame.data(), [0xaa, 0xbb]);
+        assert_eq!(timestamp_ms, None);
+
+        let (frame, _) = parse("T123456782aabb\r").unwrap().unwrap();
+        assert_eq!(
+            frame.id(),
+            Id::Extended(ExtendedId::new(0x12345678).unwrap())
+        );
+        assert_eq!(frame.data(), [0xaa, 0xbb]);
+
+        let (frame, _) = parse("t7ff0\r").unwrap().unwrap();
+        assert!(frame.data().is_empty());
+    }
+
+    #[test]
+    fn parses_remote_frames() {
+        let (frame, _) = parse("r1232\r").unwrap().unwrap();
+        assert!(matches!(frame, CanAnyFrame::Remote(_)));
+        assert_eq!(frame.dlc(), 2);
+
+        let (frame, _) = parse("R123456788\r").unwrap().unwrap();
+        assert!(frame.is_extended());
+        assert_eq!(frame.dlc(), 8);
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        let (_, timestamp_ms) = parse("t1231aaea5f\r").unwrap().unwrap();
+        assert_eq!(timestamp_ms, Some(0xea5f));
+
+        let (_, timestamp_ms) = parse("r12300010\r").unwrap().unwrap();
+        assert_eq!(timestamp_ms, Some(0x0010));
+    }
+
+    #[test]
+    fn skips_bells_and_responses() {
+        let (frame, _) = parse("\x07\x07t1231aa\r").unwrap().unwrap();
+        assert_eq!(frame.data(), [0xaa]);
+
+        for response in ["\r", "\x07", "z\r", "Z\r"] {
+            assert!(parse(response).unwrap().is_none(), "{:?}", response);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for line in [
+            "x123\r",
+            "t12\r",
+            "t123\r",
+            "t1239aabbccddeeff00112233\r",
+            "t1232aa\r",
+            "t1232aabb123\r",
+            "t1232aabb12345\r",
+            "t8001aa\r",
+            "T200000001aa\r",
+            "t12g1aa\r",
+            "t1231zz\r",
+            "t1231aaea60\r",
+            "t1231aaffff\r",
+        ] {
+            let error = parse(line).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn adapter_clock_counts_across_wraps() {
+        let mut adapter_clock = AdapterClock::new();
+
+        let first = adapter_clock.get_timestamps(59_000);
+        let origin = first.socket.unwrap();
+        assert_eq!(first.hw, Some(Duration::from_millis(59_000)));
+
+        let second = adapter_clock.get_timestamps(59_900);
+        assert_eq!(second.socket, Some(origin + Duration::from_millis(900)));
+
+        // The timestamp wrapped around from 59999 to 0
+        let third = adapter_clock.get_timestamps(100);
+        assert_eq!(third.socket, Some(origin + Duration::from_millis(1_100)));
+        assert_eq!(third.hw, Some(Duration::from_millis(100)));
+    }
+
+    /// Adapter end of a pty pair, with the path of the end the source opens.
+    /// The other end is kept open as well, as the adapter end fails to read
+    /// once no process has it open.
+    fn open_adapter() -> (File, OwnedFd, String) {
+        let pty = nix::pty::openpty(None, None).unwrap();
+        let serial_device = nix::unistd::ttyname(&pty.slave).unwrap();
+        (
+            File::from(pty.master),
+            pty.slave,
+            serial_device.to_string_lossy().into_owned(),
+        )
+    }
+
+    /// Commands the source sent to the adapter so far
+    fn read_commands(adapter: &File) -> String {
+        let mut commands = vec![];
+        let mut buf = [0; 256];
+
+        let mut poll_fds = [PollFd::new(adapter.as_fd(), PollFlags::POLLIN)];
+        while poll::poll(&mut poll_fds, 100u16).unwrap() > 0 {
+            match (&*adapter).read(&mut buf).unwrap() {
+                0 => break,
+                len => commands.extend_from_slice(&buf[..len]),
+            }
+        }
+
+        String::from_utf8(commands).unwrap()
+    }
+
+    #[test]
+    fn opens_and_closes_the_channel() {
+        let (adapter, _serial_port, serial_device) = open_adapter();
+
+        let slcan_source = SlcanSource::open(&serial_device, 500_000, false).unwrap();
+        assert_eq!(read_commands(&adapter), "C\rS6\rZ0\rO\r");
+
+        drop(slcan_source);
+        assert_eq!(read_commands(&adapter), "C\r");
+    }
+
+    #[test]
+    fn reopens_without_closing_the_new_channel() {
+        let (adapter, _serial_port, serial_device) = open_adapter();
+
+        let mut slcan_source = SlcanSource::open(&serial_device, 500_000, false).unwrap();
+        assert_eq!(read_commands(&adapter), "C\rS6\rZ0\rO\r");
+
+        slcan_source.reopen().unwrap();
+        assert_eq!(read_commands(&adapter), "C\rS6\rZ0\rO\r");
+
+        drop(slcan_source);
+        assert_eq!(read_commands(&adapter), "C\r");
+    }
+
+    #[test]
+    fn completes_lines_cut_off_by_the_timeout() {
+        let (mut adapter, _serial_port, serial_device) = open_adapter();
+        let mut slcan_source = SlcanSource::open(&serial_device, 125_000, true).unwrap();
+        assert_eq!(read_commands(&adapter), "C\rS4\rZ1\rO\r");
+
+        adapter.write_all(b"t12").unwrap();
+        let error = slcan_source.receive().err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+
+        adapter.write_all(b"32aabb0010\rz\rt4560\r").unwrap();
+        let received_frame = slcan_source.receive().unwrap();
+        assert_eq!(received_frame.frame.data(), [0xaa, 0xbb]);
+        assert_eq!(
+            received_frame.timestamps.hw,
+            Some(Duration::from_millis(0x10))
+        );
+
+        let received_frame = slcan_source.receive().unwrap();
+        assert_eq!(
+            received_frame.frame.id(),
+            Id::Standard(StandardId::new(0x456).unwrap())
+        );
+    }
+
+    #[test]
+    fn rejects_lines_which_never_end() {
+        let (mut adapter, _serial_port, serial_device) = open_adapter();
+        let mut slcan_source = SlcanSource::open(&serial_device, 500_000, false).unwrap();
+
+        adapter.write_all(&[b'x'; 100]).unwrap();
+        let error = slcan_source.receive().err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // The garbage is dropped along with the end of its line
+        adapter.write_all(b"\rt1231aa\r").unwrap();
+        assert_eq!(slcan_source.receive().unwrap().frame.data(), [0xaa]);
+    }
+}
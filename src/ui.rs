@@ -2,10 +2,18 @@ use ratatui::layout::Constraint::Percentage;
 use ratatui::layout::{Layout, Rect};
 use ratatui::style::Style;
 use ratatui::symbols::border;
-use ratatui::widgets::block::Title;
 use ratatui::{prelude::*, widgets::*};
+use socketcan::nl::CanState;
 
-use crate::App;
+use crate::draw_bit_heatmap::draw_bit_heatmap;
+use crate::draw_frame_diff::draw_frame_diff;
+use crate::draw_frame_inspector::draw_frame_inspector;
+use crate::frame::{
+    format_timestamp, get_raw_id, InterfaceSummary, FRAMES_PER_SECOND_HISTORY_SECONDS,
+};
+use crate::frame_source::InterfaceState;
+use crate::message_timeout::TimeoutEvent;
+use crate::{App, FrameView, TextInput};
 
 pub fn ui(f: &mut ratatui::Frame, app: &mut App) {
     let rects = Layout::default()
@@ -13,50 +21,183 @@ pub fn ui(f: &mut ratatui::Frame, app: &mut App) {
         .constraints([Percentage(5), Percentage(70), Percentage(25)])
         .split(f.area());
 
-    let keybindings = Title::from(Line::from(vec![
-        " Quit ".into(),
-        "<Q> ".blue().bold(),
-        " Clear Frame Info ".into(),
-        "<C> ".blue().bold(),
-        " To Top of Table ".into(),
-        "<T> ".blue().bold(),
-        " Toggle Table View ".into(),
-        "<V> ".blue().bold(),
-    ]));
+    draw_frame_tables(f, app, rects[1]);
 
-    (app.draw_frame_table)(f, app, rects[1]);
+    let keybinding_lines = get_keybinding_lines(rects[2].width);
+    let [bottom_panes, keybindings_area] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(keybinding_lines.len() as u16),
+    ])
+    .areas(rects[2]);
+    let [chart_area, timeout_log_area] =
+        Layout::horizontal([Percentage(60), Percentage(40)]).areas(bottom_panes);
 
     draw_frames_per_second_chart(
         f,
-        rects[2],
+        chart_area,
         app.frame_captor.get_frames_per_second_history(),
-        keybindings,
         app.frames_per_second_max,
     );
+    draw_timeout_log(f, timeout_log_area, app.frame_captor.get_timeout_events());
+    f.render_widget(
+        Paragraph::new(keybinding_lines).centered(),
+        keybindings_area,
+    );
 
-    let n_unique_frames = app.frame_captor.get_unique_frame_count();
+    let (receive_errors, last_receive_error) = app.frame_captor.get_receive_errors();
+    let header_counts = HeaderCounts {
+        total_frames: app.frame_captor.get_total_frame_count(),
+        unique_frames: app.frame_captor.get_unique_frame_count(),
+        error_frames: app.frame_captor.get_error_frame_count(),
+        dropped_frames: app.frame_captor.get_dropped_frame_count(),
+        receive_errors,
+        last_receive_error: last_receive_error.map(String::from),
+        evicted_frames: app.frame_captor.get_evicted_frame_count(),
+        frames_per_second: app.frame_captor.get_frames_per_second(),
+        interface_summaries: app.frame_captor.get_interface_summaries(),
+        capturing: app.frame_captor.is_capturing(),
+        new_frames_since_freeze: app.get_new_frames_since_freeze(),
+        baseline_ids: app
+            .get_changes_only_baseline()
+            .map(|baseline| baseline.get_id_count()),
+        status_message: app.status_message.clone(),
+    };
 
-    let n_total_frames = app.frame_captor.get_total_frame_count();
-    let frames_per_second = app.frame_captor.get_frames_per_second();
+    draw_header(f, rects[0], &header_counts);
 
-    draw_header(
-        f,
-        rects[0],
-        n_total_frames,
-        n_unique_frames,
-        frames_per_second,
+    if let Some(text_input) = &app.text_input {
+        draw_text_input(f, rects[1], text_input);
+    }
+}
+
+/// Keybindings shown at the bottom of the screen, as labels and their keys
+const KEYBINDINGS: [(&str, &str); 17] = [
+    ("Quit", "Q"),
+    ("Clear Frame Info", "C"),
+    ("To Top of Table", "T"),
+    ("Page", "PgUp/PgDn/Home/End"),
+    ("Go to Frame", "G"),
+    ("Filter", "/"),
+    ("Toggle Table View", "V"),
+    ("Inspect Frame", "D"),
+    ("Scroll Inspector", "</>"),
+    ("Mark Frame to Diff", "M"),
+    ("Bit Heatmap", "B"),
+    ("Scroll Heatmap", "[/]"),
+    ("Record Baseline", "R"),
+    ("Only Changes", "O"),
+    ("Freeze/Resume Display", "P"),
+    ("Stop/Start Capture", "S"),
+    ("Switch Interface", "I"),
+];
+
+/// Lays out the keybindings in as many lines as it takes to fit them in the
+/// given width, never splitting a label from its key
+fn get_keybinding_lines(width: u16) -> Vec<Line<'static>> {
+    let mut lines = vec![];
+    let mut line = Line::default();
+
+    for (label, key) in KEYBINDINGS {
+        let label = Span::from(format!(" {} ", label));
+        let key = format!("<{}> ", key).blue().bold();
+
+        if line.width() > 0 && line.width() + label.width() + key.width() > usize::from(width) {
+            lines.push(std::mem::take(&mut line));
+        }
+        line.push_span(label);
+        line.push_span(key);
+    }
+
+    lines.push(line);
+    lines
+}
+
+/// Function pointer to function for drawing a pane next to the frame table
+type DrawPaneCallback = fn(&mut ratatui::Frame, app: &App, area: Rect);
+
+/// Draws the frame table, with the panes which are shown stacked next to it.
+/// The diff against the marked frame is only shown next to the frame list,
+/// and the bit heatmap of the selected ID only next to the frame set.
+fn draw_frame_tables(f: &mut ratatui::Frame, app: &mut App, area: Rect) {
+    let mut panes: Vec<DrawPaneCallback> = vec![];
+    if app.show_frame_inspector {
+        panes.push(draw_frame_inspector);
+    }
+    match app.frame_view {
+        FrameView::FrameList if app.marked_frame.is_some() => panes.push(draw_frame_diff),
+        FrameView::FrameSet if app.show_bit_heatmap => panes.push(draw_bit_heatmap),
+        _ => {}
+    }
+
+    if panes.is_empty() {
+        return (app.draw_frame_table)(f, app, area);
+    }
+
+    let [frame_table_area, panes_area] =
+        Layout::horizontal([Percentage(60), Percentage(40)]).areas(area);
+    (app.draw_frame_table)(f, app, frame_table_area);
+
+    let pane_areas = Layout::vertical(vec![Constraint::Fill(1); panes.len()]).split(panes_area);
+    for (draw_pane, pane_area) in panes.into_iter().zip(pane_areas.iter()) {
+        draw_pane(f, app, *pane_area);
+    }
+}
+
+/// Counters shown in the header
+struct HeaderCounts {
+    total_frames: usize,
+    unique_frames: usize,
+    error_frames: usize,
+    dropped_frames: usize,
+    /// Times the frame sources failed to receive, with the latest failure
+    receive_errors: usize,
+    last_receive_error: Option<String>,
+    evicted_frames: usize,
+    frames_per_second: usize,
+    interface_summaries: Vec<InterfaceSummary>,
+    capturing: bool,
+    /// Only set while the display is frozen
+    new_frames_since_freeze: Option<u64>,
+    /// Number of IDs in the baseline, only set while only the frames which
+    /// deviate from it are shown
+    baseline_ids: Option<usize>,
+    status_message: Option<String>,
+}
+
+/// Input prompt, drawn on top of the frame table
+fn draw_text_input(f: &mut ratatui::Frame, area: Rect, text_input: &TextInput) {
+    let [_, input_area, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(3),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+    let [_, input_area, _] = Layout::horizontal([
+        Constraint::Percentage(25),
+        Constraint::Percentage(50),
+        Constraint::Percentage(25),
+    ])
+    .areas(input_area);
+
+    let input = Paragraph::new(format!("{}_", text_input.text)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Double)
+            .title(text_input.prompt.get_title()),
     );
+
+    f.render_widget(Clear, input_area);
+    f.render_widget(input, input_area);
 }
 
 fn draw_frames_per_second_chart(
     frame: &mut ratatui::Frame,
     area: Rect,
     data: Vec<(f64, f64)>,
-    keybindings: Title<'_>,
     frames_per_second_max: u32,
 ) {
     let x_limit_lo = 0.0;
-    let x_limit_hi = 300.0;
+    let x_limit_hi = FRAMES_PER_SECOND_HISTORY_SECONDS as f64;
 
     let y_limit_lo = 0.0;
     let y_limit_hi = frames_per_second_max as f64;
@@ -96,34 +237,183 @@ fn draw_frames_per_second_chart(
             Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Double)
-                .border_set(border::THICK)
-                .title(
-                    keybindings
-                        .alignment(Alignment::Center)
-                        .position(block::Position::Bottom),
-                ),
+                .border_set(border::THICK),
         );
 
     frame.render_widget(chart, area);
 }
 
-fn draw_header(
-    f: &mut ratatui::Frame,
-    area: Rect,
-    total_frame_count: usize,
-    n_unique_frames: usize,
-    frames_per_second: usize,
-) {
-    let header = Paragraph::new(Line::from(format!(
-        "Unique Frame IDs: {}, Total Frame Count {}, Frames Per Second: {}",
-        n_unique_frames, total_frame_count, frames_per_second
-    )))
-    .centered()
-    .block(
+/// Log of the IDs which stopped arriving, newest first. Ongoing timeouts
+/// stand out, as the ID is still missing.
+fn draw_timeout_log(f: &mut ratatui::Frame, area: Rect, timeout_events: &[TimeoutEvent]) {
+    let timeout_lines = timeout_events
+        .iter()
+        .rev()
+        .map(|timeout_event| {
+            let (interface, id) = &timeout_event.key;
+            let description = format!(
+                "{} 0x{:x} (timeout {:.3} ms): missing from {}",
+                interface,
+                get_raw_id(*id),
+                timeout_event.timeout.as_secs_f64() * 1000.0,
+                format_timestamp(timeout_event.start)
+            );
+
+            match timeout_event.end {
+                Some(end) => Line::from(format!(
+                    "{} to {}, {:.3} s",
+                    description,
+                    format_timestamp(end),
+                    end.saturating_sub(timeout_event.start).as_secs_f64()
+                )),
+                None => Line::styled(
+                    format!("{}, still missing", description),
+                    Style::default().fg(Color::Red).bold(),
+                ),
+            }
+        })
+        .collect::<Vec<Line>>();
+
+    let timeout_log = Paragraph::new(timeout_lines).block(
         Block::default()
             .borders(Borders::ALL)
-            .border_type(BorderType::Double),
+            .border_type(BorderType::Double)
+            .border_set(border::THICK)
+            .title(format!(" Message Timeouts ({}) ", timeout_events.len())),
     );
 
+    f.render_widget(timeout_log, area);
+}
+
+/// Frame count of an interface, along with its state if it is known. A lost
+/// interface or a controller in bus-off stands out, as no frames arrive then.
+fn get_interface_summary_span(summary: &InterfaceSummary) -> Span<'static> {
+    let Some(state) = summary.state else {
+        return Span::from(format!("{}: {}", summary.interface, summary.frame_count));
+    };
+
+    let style = match state {
+        InterfaceState::Down | InterfaceState::Up(Some(CanState::BusOff)) => {
+            Style::default().fg(Color::Red).bold()
+        }
+        InterfaceState::Up(Some(CanState::ErrorWarning | CanState::ErrorPassive)) => {
+            Style::default().fg(Color::Yellow)
+        }
+        InterfaceState::Up(_) => Style::default(),
+    };
+
+    Span::styled(
+        format!("{} ({}): {}", summary.interface, state, summary.frame_count),
+        style,
+    )
+}
+
+fn draw_header(f: &mut ratatui::Frame, area: Rect, counts: &HeaderCounts) {
+    let error_frames_style = match counts.error_frames {
+        0 => Style::default(),
+        _ => Style::default().fg(Color::Red).bold(),
+    };
+
+    // Always shown, so that a zero count confirms nothing was missed
+    let dropped_frames_style = match counts.dropped_frames {
+        0 => Style::default(),
+        _ => Style::default().fg(Color::Red).bold(),
+    };
+
+    let mut header_spans = vec![];
+
+    if let Some(new_frames) = counts.new_frames_since_freeze {
+        header_spans.push(Span::styled(
+            format!("FROZEN, {} new frames since pause | ", new_frames),
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::LightCyan)
+                .bold(),
+        ));
+    }
+
+    if let Some(baseline_ids) = counts.baseline_ids {
+        header_spans.push(Span::styled(
+            format!("CHANGES ONLY, baseline of {} IDs | ", baseline_ids),
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::LightMagenta)
+                .bold(),
+        ));
+    }
+
+    if !counts.capturing {
+        header_spans.push(Span::styled(
+            "Capture Stopped | ",
+            Style::default().fg(Color::Yellow).bold(),
+        ));
+    }
+
+    header_spans.extend([
+        Span::from(format!(
+            "Unique Frame IDs: {}, Total Frame Count {}, ",
+            counts.unique_frames, counts.total_frames
+        )),
+        Span::styled(
+            format!("Error Frames: {}", counts.error_frames),
+            error_frames_style,
+        ),
+        Span::from(", "),
+        Span::styled(
+            format!("Dropped Frames: {}", counts.dropped_frames),
+            dropped_frames_style,
+        ),
+    ]);
+
+    // Only shown once a source failed to receive, which should never happen
+    if let Some(last_receive_error) = &counts.last_receive_error {
+        header_spans.push(Span::styled(
+            format!(
+                ", Receive Errors: {} (last: {})",
+                counts.receive_errors, last_receive_error
+            ),
+            Style::default().fg(Color::Red).bold(),
+        ));
+    }
+
+    // Only shown once frames have been evicted, as most captures never
+    // reach the history limit
+    if counts.evicted_frames > 0 {
+        header_spans.push(Span::styled(
+            format!(", Evicted Frames: {}", counts.evicted_frames),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+
+    header_spans.push(Span::from(format!(
+        ", Frames Per Second: {} |",
+        counts.frames_per_second
+    )));
+
+    for (i, summary) in counts.interface_summaries.iter().enumerate() {
+        let separator = if i == 0 { " " } else { ", " };
+        header_spans.push(Span::from(separator));
+        header_spans.push(get_interface_summary_span(summary));
+    }
+
+    let mut header_block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Double);
+
+    // Shown on the border, so that a long line of counters can not cut it off
+    if let Some(status_message) = &counts.status_message {
+        header_block = header_block.title_bottom(
+            Line::styled(
+                format!(" {} ", status_message),
+                Style::default().fg(Color::Red).bold(),
+            )
+            .centered(),
+        );
+    }
+
+    let header = Paragraph::new(Line::from(header_spans))
+        .centered()
+        .block(header_block);
+
     f.render_widget(header, area);
 }